@@ -1,24 +1,1763 @@
 mod scope;
 use crate::parse::{
-    expression::*,
-    for_loop::{ForLoop, ForLoopCondition},
-    instruction::FunctionBody,
-    scope::Function,
+    error::SyntaxError,
+    expression::{Expr, ExprKind, MutationKind},
+    for_loop::{ForLoop, ForLoopCondition, LoopTarget, Prerequisite},
+    identifier::Identifier,
+    instruction::{Catch, FunctionBody, Statement, StatementKind},
+    obj::{self, ArrowBody, ObjectKind},
+    scope::{DeclarationKind, Variable},
+    switch::Switch,
     Ast,
 };
-use crate::vm::Instruction;
-use std::collections::HashMap;
+use crate::vm::{Capture, Instruction, InstructionAddress, Object, StackAddress};
+use scope::Scope;
+use std::fmt;
+use std::rc::Rc;
 
-pub fn generate_code(ast: Ast) -> Vec<Instruction> {
-    let functions = get_all_functions(&ast, String::new());
+/// Reasons for the code generation to fail
+#[derive(Debug, Clone, PartialEq)]
+pub enum CompileError {
+    /// Variable is used, but never declared
+    UndefinedVariable(Identifier),
+    /// `break` outside of any loop or switch
+    IllegalBreak,
+    /// `continue` outside of any loop, or targeting a label of something else
+    IllegalContinue,
+    /// `break` or `continue` targeting a label, which no surrounding statement has
+    UndefinedLabel(Identifier),
+    /// Assignment to a variable declared with `const`
+    ConstAssignment(Identifier),
+    /// `let` or `const` declaring a name twice in the same block,
+    /// or a name also declared by `var` or a function there
+    Redeclaration(Identifier),
+    /// Statement, the parser recovered from
+    SyntaxError(SyntaxError),
 }
 
-fn get_all_functions<'a>(ast: &'a Ast, scope: String) -> HashMap<String, &'a Function> {
-    ast.functions.iter().fold(
-        map: HashMap::new(),
-        |acc: HashMap<String, &'a Function>, f: &'a FunctionBody| {
-            map[format!({}{}, &scope, f.identifier.0] = get_all_functions(:wq
+impl fmt::Display for CompileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CompileError::UndefinedVariable(identifier) => {
+                write!(f, "{} is not defined", identifier.0)
+            }
+            CompileError::IllegalBreak => write!(f, "break outside of a loop or switch"),
+            CompileError::IllegalContinue => write!(f, "continue outside of a loop"),
+            CompileError::UndefinedLabel(label) => write!(f, "undefined label {}", label.0),
+            CompileError::ConstAssignment(identifier) => {
+                write!(f, "assignment to constant variable {}", identifier.0)
+            }
+            CompileError::Redeclaration(identifier) => {
+                write!(f, "{} has already been declared", identifier.0)
+            }
+            CompileError::SyntaxError(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+/// Translate the Ast into Instructions for the `VirtualMachine`.
+///
+/// The toplevel code starts at address 0 and ends with a `Return`.
+/// Variables of the toplevel live at the bottom of the stack,
+/// so that every function is able to reach them using `LoadGlobal` and `StoreGlobal`.
+pub fn generate_code(ast: &Ast) -> Result<Vec<Instruction>, CompileError> {
+    let mut compiler = Compiler {
+        code: Vec::new(),
+        scope: Scope::new(),
+        functions: Vec::new(),
+    };
+
+    compiler.function_body(&[], ast)?;
+
+    Ok(compiler.code)
+}
+
+/// Location of a declared variable
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Binding {
+    /// Nesting depth of the declaring function, 0 being the toplevel
+    function: usize,
+    address: StackAddress,
+    /// Declared using `const`
+    constant: bool,
+    /// Toplevel variable outside of any nested block.
+    /// It exists only once, so every function reaches it using `LoadGlobal`.
+    global: bool,
+}
+
+/// Variable or property, which is assigned to
+enum Place<'a> {
+    Variable(&'a Identifier),
+    /// Object and key are on the stack
+    Property,
+}
+
+/// The way a variable is reached from the function being compiled
+enum Access {
+    Local(StackAddress),
+    Global(StackAddress),
+    Captured(usize),
+}
+
+#[derive(Default)]
+struct FunctionState<'a> {
+    /// Stack slots used by arguments, variables and temporary values
+    slots: usize,
+    /// Depth of the scope holding the declarations of the function body
+    body_scope: usize,
+    captures: Vec<(Binding, Capture)>,
+    /// Statements currently compiled, which `break` and `continue` may refer to
+    targets: Vec<JumpTarget>,
+    /// Labels waiting for the loop they belong to
+    labels: Vec<Identifier>,
+    /// Exception handlers installed by the `try` blocks currently compiled,
+    /// with the `finally` block to run when leaving them
+    tries: Vec<Option<&'a FunctionBody>>,
+    /// Jumps out of the optional chains currently compiled, innermost last
+    chains: Vec<Vec<InstructionAddress>>,
+}
+
+#[derive(PartialEq)]
+enum TargetKind {
+    Loop,
+    Switch,
+    /// Any other labeled statement, which only `break` with label may leave
+    Labeled,
+}
+
+/// Jumps of `break` and `continue` statements, waiting for their target
+struct JumpTarget {
+    kind: TargetKind,
+    labels: Vec<Identifier>,
+    /// Number of `try` blocks around the statement
+    tries: usize,
+    breaks: Vec<InstructionAddress>,
+    continues: Vec<InstructionAddress>,
+}
+
+struct Compiler<'a> {
+    code: Vec<Instruction>,
+    scope: Scope<Identifier, Binding>,
+    /// Functions currently compiled, innermost last
+    functions: Vec<FunctionState<'a>>,
+}
+
+impl<'a> Compiler<'a> {
+    fn emit(&mut self, instruction: Instruction) -> InstructionAddress {
+        self.code.push(instruction);
+        self.code.len() - 1
+    }
+
+    /// Let the jump at `at` point to the next instruction emitted
+    fn patch(&mut self, at: InstructionAddress) {
+        let target = self.code.len();
+        self.patch_to(at, target);
+    }
+
+    fn patch_to(&mut self, at: InstructionAddress, target: InstructionAddress) {
+        match &mut self.code[at] {
+            Instruction::JumpStatic(address)
+            | Instruction::JumpConditional(address)
+            | Instruction::Next(address)
+            | Instruction::Try(address) => *address = target,
+            _ => unreachable!("only jumps can be patched"),
+        }
+    }
+
+    fn function(&mut self) -> &mut FunctionState<'a> {
+        self.functions.last_mut().expect("no function compiled")
+    }
+
+    /// Reserve a slot on the stack frame of the current function
+    fn allocate(&mut self) -> StackAddress {
+        let function = self.function();
+        function.slots += 1;
+        function.slots - 1
+    }
+
+    fn declare(&mut self, identifier: &Identifier, constant: bool) -> StackAddress {
+        let address = self.allocate();
+        let function = self.functions.len() - 1;
+        let binding = Binding {
+            function,
+            address,
+            constant,
+            global: function == 0 && self.scope.depth() <= self.functions[0].body_scope,
+        };
+        self.scope.insert(identifier.clone(), binding);
+        address
+    }
+
+    fn resolve(&mut self, identifier: &Identifier) -> Result<Access, CompileError> {
+        let binding = *self
+            .scope
+            .get(identifier)
+            .ok_or_else(|| CompileError::UndefinedVariable(identifier.clone()))?;
+        let current = self.functions.len() - 1;
+
+        Ok(if binding.function == current {
+            Access::Local(binding.address)
+        } else if binding.global {
+            Access::Global(binding.address)
+        } else {
+            Access::Captured(self.capture(current, binding))
+        })
+    }
+
+    /// Index of `binding` in the enviroment of `function`.
+    /// Functions in between capture the variable as well, to pass it on.
+    fn capture(&mut self, function: usize, binding: Binding) -> usize {
+        let captures = &self.functions[function].captures;
+        if let Some(index) = captures.iter().position(|(b, _)| *b == binding) {
+            return index;
+        }
+
+        let capture = if binding.function == function - 1 {
+            Capture::Local(binding.address)
+        } else {
+            Capture::Captured(self.capture(function - 1, binding))
+        };
+
+        let captures = &mut self.functions[function].captures;
+        captures.push((binding, capture));
+        captures.len() - 1
+    }
+
+    fn load(&mut self, identifier: &Identifier) -> Result<(), CompileError> {
+        let instruction = match self.resolve(identifier)? {
+            Access::Local(address) => Instruction::Load(address),
+            Access::Global(address) => Instruction::LoadGlobal(address),
+            Access::Captured(index) => Instruction::LoadCaptured(index),
+        };
+        self.emit(instruction);
+        Ok(())
+    }
+
+    fn store(&mut self, identifier: &Identifier) -> Result<(), CompileError> {
+        if self.scope.get(identifier).is_some_and(|b| b.constant) {
+            return Err(CompileError::ConstAssignment(identifier.clone()));
+        }
+
+        let instruction = match self.resolve(identifier)? {
+            Access::Local(address) => Instruction::Store(address),
+            Access::Global(address) => Instruction::StoreGlobal(address),
+            Access::Captured(index) => Instruction::StoreCaptured(index),
+        };
+        self.emit(instruction);
+        Ok(())
+    }
+
+    /// Emit the code of a function at the current address
+    /// and return the variables it needs to capture
+    fn function_body(
+        &mut self,
+        arguments: &'a [Identifier],
+        body: &'a FunctionBody,
+    ) -> Result<Vec<Capture>, CompileError> {
+        self.function_frame(arguments, |compiler| {
+            // `var` declarations are visible in the whole function
+            let mut hoisted = Vec::new();
+            var_names(body, &mut hoisted);
+            for identifier in hoisted {
+                if !arguments.contains(identifier) {
+                    let address = compiler.declare(identifier, false);
+                    compiler.emit(Instruction::Push(Object::Undefined));
+                    compiler.emit(Instruction::Bind(address));
+                }
+            }
+
+            compiler.block(body)
+        })
+    }
+
+    /// Emit the code of an arrow function at the current address,
+    /// a concise body is the same as `{ return expr }`
+    fn arrow_body(
+        &mut self,
+        arguments: &'a [Identifier],
+        body: &'a ArrowBody,
+    ) -> Result<Vec<Capture>, CompileError> {
+        match body {
+            ArrowBody::Block(body) => self.function_body(arguments, body),
+            ArrowBody::Expression(expr) => self.function_frame(arguments, |compiler| {
+                compiler.expression(expr)?;
+                compiler.emit(Instruction::Return);
+                Ok(())
+            }),
+        }
+    }
+
+    /// Set up the frame of a function around the code emitted by `body`
+    fn function_frame(
+        &mut self,
+        arguments: &'a [Identifier],
+        body: impl FnOnce(&mut Self) -> Result<(), CompileError>,
+    ) -> Result<Vec<Capture>, CompileError> {
+        self.functions.push(FunctionState::default());
+        self.scope.push();
+        // The body is a block of it's own
+        self.function().body_scope = self.scope.depth() + 1;
+
+        let enter = self.emit(Instruction::Enter {
+            arguments: arguments.len(),
+            locals: 0,
+        });
+        for argument in arguments {
+            self.declare(argument, false);
+        }
+
+        let result = body(self);
+        self.emit(Instruction::Push(Object::Undefined));
+        self.emit(Instruction::Return);
+
+        self.scope.pop();
+        let function = self.functions.pop().expect("no function compiled");
+        result?;
+
+        if let Instruction::Enter { locals, .. } = &mut self.code[enter] {
+            *locals = function.slots - arguments.len();
+        }
+
+        Ok(function.captures.into_iter().map(|(_, c)| c).collect())
+    }
+
+    /// Closures are placed right where they are created,
+    /// the surrounding code jumps over them
+    fn closure(
+        &mut self,
+        body: impl FnOnce(&mut Self) -> Result<Vec<Capture>, CompileError>,
+    ) -> Result<(), CompileError> {
+        let skip = self.emit(Instruction::JumpStatic(0));
+        let function = self.code.len();
+        let captures = body(self)?;
+        self.patch(skip);
+        self.emit(Instruction::Closure { function, captures });
+        Ok(())
+    }
+
+    fn block(&mut self, body: &'a FunctionBody) -> Result<(), CompileError> {
+        self.scope.push();
+        let result = self.block_items(body);
+        self.scope.pop();
+        result
+    }
+
+    fn block_items(&mut self, body: &'a FunctionBody) -> Result<(), CompileError> {
+        self.hoist(std::iter::once(body))?;
+        for statement in &body.instructions {
+            self.statement(statement)?;
+        }
+
+        Ok(())
+    }
+
+    /// Declare the variables and functions of `blocks` in the current scope
+    fn hoist(
+        &mut self,
+        blocks: impl Iterator<Item = &'a FunctionBody> + Clone,
+    ) -> Result<(), CompileError> {
+        // `var` declarations pass through the block on their way to the function scope,
+        // so they clash with `let` and `const` of the same name
+        let mut vars = Vec::new();
+        for body in blocks.clone() {
+            var_names(body, &mut vars);
+        }
+
+        // Every declaration is visible in the whole block,
+        // so that hoisted functions may use variables declared later on
+        let mut lexical = Vec::new();
+        for statement in blocks.clone().flat_map(|body| &body.instructions) {
+            if let StatementKind::Declaration(variables) = &statement.kind {
+                for variable in variables {
+                    if variable.kind != DeclarationKind::Var {
+                        if vars.contains(&&variable.identifier) {
+                            return Err(CompileError::Redeclaration(variable.identifier.clone()));
+                        }
+                        lexical.push(&variable.identifier);
+                    }
+                }
+                self.declare_block_scoped(variables)?;
+            }
+        }
+
+        for function in blocks.flat_map(|body| &body.functions) {
+            if lexical.contains(&&function.identifier) {
+                return Err(CompileError::Redeclaration(function.identifier.clone()));
+            }
+            let address = self.declare(&function.identifier, false);
+            self.closure(|compiler| compiler.function_body(&function.arguments, &function.body))?;
+            self.emit(Instruction::Bind(address));
+        }
+
+        Ok(())
+    }
+
+    /// Declare `let` and `const` variables in the current scope,
+    /// `var` is taken care of by `function_body`
+    fn declare_block_scoped(&mut self, variables: &[Variable]) -> Result<(), CompileError> {
+        for variable in variables {
+            if variable.kind != DeclarationKind::Var {
+                if self.scope.defines(&variable.identifier) {
+                    return Err(CompileError::Redeclaration(variable.identifier.clone()));
+                }
+                let constant = variable.kind == DeclarationKind::Const;
+                self.declare(&variable.identifier, constant);
+            }
+        }
+        Ok(())
+    }
+
+    fn declaration(&mut self, variable: &'a Variable) -> Result<(), CompileError> {
+        // Hoisted variables already exist, they are only assigned
+        if variable.kind == DeclarationKind::Var {
+            if let Some(expr) = &variable.assign {
+                self.expression(expr)?;
+                self.store(&variable.identifier)?;
+            }
+            return Ok(());
+        }
+
+        match &variable.assign {
+            Some(expr) => self.expression(expr)?,
+            None => {
+                self.emit(Instruction::Push(Object::Undefined));
+            }
+        }
+
+        self.bind(&variable.identifier)
+    }
+
+    /// Start a fresh binding of a block scoped variable with the value on top
+    fn bind(&mut self, identifier: &Identifier) -> Result<(), CompileError> {
+        match self.resolve(identifier)? {
+            Access::Local(address) => {
+                self.emit(Instruction::Bind(address));
+                Ok(())
+            }
+            _ => unreachable!("variables are always declared by the current function"),
+        }
+    }
+
+    fn statement(&mut self, statement: &'a Statement) -> Result<(), CompileError> {
+        match &statement.kind {
+            StatementKind::Declaration(variables) => {
+                for variable in variables {
+                    self.declaration(variable)?;
+                }
+            }
+            StatementKind::Return(value) => {
+                match value {
+                    Some(expr) => self.expression(expr)?,
+                    None => {
+                        self.emit(Instruction::Push(Object::Undefined));
+                    }
+                }
+
+                // The value waits for the `finally` blocks to finish
+                if !self.function().tries.is_empty() {
+                    let value = self.allocate();
+                    self.emit(Instruction::Bind(value));
+                    self.leave_tries(0)?;
+                    self.emit(Instruction::Load(value));
+                }
+                self.emit(Instruction::Return);
+            }
+            StatementKind::If {
+                condition,
+                body,
+                else_branch,
+            } => {
+                self.expression(condition)?;
+                let skip_body = self.emit(Instruction::JumpConditional(0));
+                self.block(body)?;
+
+                if let Some(else_branch) = else_branch {
+                    let skip_else = self.emit(Instruction::JumpStatic(0));
+                    self.patch(skip_body);
+                    self.block(else_branch)?;
+                    self.patch(skip_else);
+                } else {
+                    self.patch(skip_body);
+                }
+            }
+            StatementKind::While { condition, body } => {
+                let start = self.code.len();
+                self.expression(condition)?;
+                let exit = self.emit(Instruction::JumpConditional(0));
+                let jumps = self.loop_body(body)?;
+                self.emit(Instruction::JumpStatic(start));
+                self.patch(exit);
+                self.close_loop(jumps, start);
+            }
+            StatementKind::DoWhile { body, condition } => {
+                let start = self.code.len();
+                let jumps = self.loop_body(body)?;
+                let next = self.code.len();
+                self.expression(condition)?;
+                self.emit(Instruction::Not);
+                self.emit(Instruction::JumpConditional(start));
+                self.close_loop(jumps, next);
+            }
+            StatementKind::Block(body) => self.block(body)?,
+            StatementKind::For(for_loop) => self.for_loop(for_loop)?,
+            StatementKind::Switch(switch) => self.switch(switch)?,
+            StatementKind::Labeled { label, body } => {
+                self.function().labels.push(label.clone());
+                match &body.kind {
+                    StatementKind::While { .. }
+                    | StatementKind::DoWhile { .. }
+                    | StatementKind::For(_)
+                    | StatementKind::Labeled { .. } => self.statement(body)?,
+                    _ => {
+                        let target =
+                            self.jump_target(TargetKind::Labeled, |c| c.statement(body))?;
+                        self.close_block(target);
+                    }
+                }
+            }
+            StatementKind::Break(label) => {
+                let tries = self.target(label.as_ref(), false)?.tries;
+                self.leave_tries(tries)?;
+                let jump = self.emit(Instruction::JumpStatic(0));
+                self.target(label.as_ref(), false)?.breaks.push(jump);
+            }
+            StatementKind::Continue(label) => {
+                let tries = self.target(label.as_ref(), true)?.tries;
+                self.leave_tries(tries)?;
+                let jump = self.emit(Instruction::JumpStatic(0));
+                self.target(label.as_ref(), true)?.continues.push(jump);
+            }
+            StatementKind::Throw(value) => {
+                self.expression(value)?;
+                self.emit(Instruction::Throw);
+            }
+            StatementKind::Try {
+                body,
+                catch,
+                finally,
+            } => self.try_statement(body, catch.as_deref(), finally.as_ref())?,
+            StatementKind::Expression(expr) => {
+                self.expression(expr)?;
+                self.emit(Instruction::Pop);
+            }
+            StatementKind::Empty => {}
+            StatementKind::Error(error) => return Err(CompileError::SyntaxError(error.clone())),
+        }
+
+        Ok(())
+    }
+
+    /// Exceptions thrown by `body` continue in `catch`.
+    /// `finally` runs after both of them, no matter how they are left.
+    fn try_statement(
+        &mut self,
+        body: &'a FunctionBody,
+        catch: Option<&'a Catch>,
+        finally: Option<&'a FunctionBody>,
+    ) -> Result<(), CompileError> {
+        let handler = self.emit(Instruction::Try(0));
+        self.protected(finally, |c| c.block(body))?;
+        self.emit(Instruction::EndTry);
+
+        // Handler running the `finally` block for uncaught exceptions
+        let rethrow = match catch {
+            Some(catch) => {
+                let skip = self.emit(Instruction::JumpStatic(0));
+                self.patch(handler);
+                let rethrow = match finally {
+                    Some(_) => {
+                        let rethrow = self.emit(Instruction::Try(0));
+                        self.protected(finally, |c| c.catch(catch))?;
+                        self.emit(Instruction::EndTry);
+                        Some(rethrow)
+                    }
+                    None => {
+                        self.catch(catch)?;
+                        None
+                    }
+                };
+                self.patch(skip);
+                rethrow
+            }
+            None => Some(handler),
+        };
+
+        if let (Some(finally), Some(rethrow)) = (finally, rethrow) {
+            self.block(finally)?;
+            let end = self.emit(Instruction::JumpStatic(0));
+
+            self.patch(rethrow);
+            let exception = self.allocate();
+            self.emit(Instruction::Bind(exception));
+            self.block(finally)?;
+            self.emit(Instruction::Load(exception));
+            self.emit(Instruction::Throw);
+            self.patch(end);
+        }
+
+        Ok(())
+    }
+
+    /// Run `compile` inside a `try` block, which has an exception handler installed
+    fn protected(
+        &mut self,
+        finally: Option<&'a FunctionBody>,
+        compile: impl FnOnce(&mut Self) -> Result<(), CompileError>,
+    ) -> Result<(), CompileError> {
+        self.function().tries.push(finally);
+        let result = compile(self);
+        self.function().tries.pop();
+        result
+    }
+
+    /// Exception handler, with the exception on top of the stack
+    fn catch(&mut self, catch: &'a Catch) -> Result<(), CompileError> {
+        self.scope.push();
+        let result = match &catch.parameter {
+            Some(parameter) => {
+                let address = self.declare(parameter, false);
+                self.emit(Instruction::Bind(address));
+                self.block(&catch.body)
+            }
+            None => {
+                self.emit(Instruction::Pop);
+                self.block(&catch.body)
+            }
+        };
+        self.scope.pop();
+        result
+    }
+
+    /// Jump out of the innermost `try` blocks, until only `depth` are left.
+    /// Their exception handlers are removed and their `finally` blocks run.
+    fn leave_tries(&mut self, depth: usize) -> Result<(), CompileError> {
+        let tries = self.function().tries.clone();
+        let mut result = Ok(());
+        for (i, finally) in tries.iter().enumerate().skip(depth).rev() {
+            self.emit(Instruction::EndTry);
+            // `finally` blocks are outside of their `try` block
+            self.function().tries.truncate(i);
+            if let Some(finally) = finally {
+                result = self.block(finally);
+                if result.is_err() {
+                    break;
+                }
+            }
+        }
+
+        self.function().tries = tries;
+        result
+    }
+
+    /// Compile the body of a loop, collecting it's `break` and `continue` statements
+    fn loop_body(&mut self, body: &'a FunctionBody) -> Result<JumpTarget, CompileError> {
+        self.jump_target(TargetKind::Loop, |c| c.block(body))
+    }
+
+    /// Run `compile`, collecting the `break` and `continue` statements
+    /// jumping to the end of the code emitted
+    fn jump_target(
+        &mut self,
+        kind: TargetKind,
+        compile: impl FnOnce(&mut Self) -> Result<(), CompileError>,
+    ) -> Result<JumpTarget, CompileError> {
+        let labels = std::mem::take(&mut self.function().labels);
+        let tries = self.function().tries.len();
+        self.function().targets.push(JumpTarget {
+            kind,
+            labels,
+            tries,
+            breaks: Vec::new(),
+            continues: Vec::new(),
+        });
+        let result = compile(self);
+        let target = self.function().targets.pop().expect("jump target vanished");
+        result.map(|_| target)
+    }
+
+    /// Statement a `break` or `continue` leaves.
+    /// Without label, it's the innermost loop, or switch for `break`.
+    fn target(
+        &mut self,
+        label: Option<&Identifier>,
+        continues: bool,
+    ) -> Result<&mut JumpTarget, CompileError> {
+        let mut targets = self.function().targets.iter_mut().rev();
+        let target = match label {
+            Some(label) => targets.find(|target| target.labels.contains(label)),
+            None => targets.find(|target| match target.kind {
+                TargetKind::Loop => true,
+                TargetKind::Switch => !continues,
+                TargetKind::Labeled => false,
+            }),
+        };
+
+        match (target, label) {
+            (Some(target), _) if continues && target.kind != TargetKind::Loop => {
+                Err(CompileError::IllegalContinue)
+            }
+            (Some(target), _) => Ok(target),
+            (None, Some(label)) => Err(CompileError::UndefinedLabel(label.clone())),
+            (None, None) if continues => Err(CompileError::IllegalContinue),
+            (None, None) => Err(CompileError::IllegalBreak),
+        }
+    }
+
+    /// Let `break` jump right behind a statement, which `continue` can't target
+    fn close_block(&mut self, jumps: JumpTarget) {
+        debug_assert!(jumps.continues.is_empty());
+        self.close_loop(jumps, 0);
+    }
+
+    /// Let `break` jump right behind the loop and `continue` to `next`
+    fn close_loop(&mut self, jumps: JumpTarget, next: InstructionAddress) {
+        let end = self.code.len();
+        for jump in jumps.breaks {
+            self.patch_to(jump, end);
+        }
+        for jump in jumps.continues {
+            self.patch_to(jump, next);
+        }
+    }
+
+    /// Constant cases are looked up in a `JumpTable`,
+    /// any other case is compared one after another
+    fn switch(&mut self, switch: &'a Switch) -> Result<(), CompileError> {
+        self.expression(&switch.discriminant)?;
+
+        let constant_cases = switch
+            .cases
+            .iter()
+            .filter_map(|case| case.test.as_deref())
+            .all(|test| constant(test).is_some());
+
+        // Every clause shares the same scope
+        self.scope.push();
+        let result = self.hoist(switch.cases.iter().map(|case| &case.body));
+        let result = result.and_then(|_| {
+            if constant_cases {
+                self.jump_table(switch)
+            } else {
+                self.comparisons(switch)
+            }
+        });
+        self.scope.pop();
+        result
+    }
+
+    fn jump_table(&mut self, switch: &'a Switch) -> Result<(), CompileError> {
+        let table = self.emit(Instruction::JumpTable {
+            cases: Vec::new(),
+            default: 0,
+        });
+
+        let clauses = self.clauses(switch)?;
+        let end = self.code.len();
+
+        let mut cases = Vec::new();
+        let mut default = end;
+        for (case, address) in switch.cases.iter().zip(clauses) {
+            match case.test.as_deref().map(constant) {
+                Some(Some(constant)) => cases.push((constant, address)),
+                Some(None) => unreachable!("only constant cases are looked up"),
+                None => default = address,
+            }
+        }
+        self.code[table] = Instruction::JumpTable { cases, default };
+        Ok(())
+    }
+
+    fn comparisons(&mut self, switch: &'a Switch) -> Result<(), CompileError> {
+        let discriminant = self.allocate();
+        self.emit(Instruction::Bind(discriminant));
+
+        // Jumps to the clauses, if the discriminant matches
+        let mut jumps = Vec::new();
+        for case in &switch.cases {
+            if let Some(test) = &case.test {
+                self.emit(Instruction::Load(discriminant));
+                self.expression(test)?;
+                self.emit(Instruction::StrictNotEqual);
+                jumps.push(Some(self.emit(Instruction::JumpConditional(0))));
+            } else {
+                jumps.push(None);
+            }
+        }
+        let no_match = self.emit(Instruction::JumpStatic(0));
+
+        let clauses = self.clauses(switch)?;
+        let mut default = self.code.len();
+        for (jump, address) in jumps.into_iter().zip(clauses) {
+            match jump {
+                Some(jump) => self.patch_to(jump, address),
+                None => default = address,
+            }
+        }
+        self.patch_to(no_match, default);
+        Ok(())
+    }
+
+    /// Compile the statements of all clauses one after another,
+    /// so that execution falls through to the next clause.
+    /// Returns the address of each clause.
+    fn clauses(&mut self, switch: &'a Switch) -> Result<Vec<InstructionAddress>, CompileError> {
+        let mut addresses = Vec::new();
+        let target = self.jump_target(TargetKind::Switch, |c| {
+            switch.cases.iter().try_for_each(|case| {
+                addresses.push(c.code.len());
+                case.body
+                    .instructions
+                    .iter()
+                    .try_for_each(|statement| c.statement(statement))
+            })
+        })?;
+
+        self.close_block(target);
+        Ok(addresses)
+    }
+
+    fn for_loop(&mut self, for_loop: &'a ForLoop) -> Result<(), CompileError> {
+        match &for_loop.condition {
+            ForLoopCondition::CStyle {
+                prerequisite,
+                condition,
+                mutation,
+            } => {
+                self.scope.push();
+                let result = self.c_style_loop(
+                    prerequisite.as_ref(),
+                    condition.as_deref(),
+                    mutation.as_deref(),
+                    &for_loop.body,
+                );
+                self.scope.pop();
+                result
+            }
+            ForLoopCondition::ElemOfIter { element, iter } => {
+                self.expression(iter)?;
+                self.emit(Instruction::Iterate);
+                self.iteration(element, &for_loop.body)
+            }
+            ForLoopCondition::KeyInIter { key, iter } => {
+                self.expression(iter)?;
+                self.emit(Instruction::IterateKeys);
+                self.iteration(key, &for_loop.body)
+            }
+        }
+    }
+
+    /// Loop over the iterator on top of the stack.
+    /// `let` and `const` create a fresh binding for every iteration.
+    fn iteration(
+        &mut self,
+        target: &'a LoopTarget,
+        body: &'a FunctionBody,
+    ) -> Result<(), CompileError> {
+        let iterator = self.allocate();
+        self.emit(Instruction::Bind(iterator));
+
+        let start = self.emit(Instruction::Load(iterator));
+        let exit = self.emit(Instruction::Next(0));
+
+        self.scope.push();
+        let result = match target {
+            LoopTarget::Declaration(variable) => match variable.kind {
+                DeclarationKind::Var => self.store(&variable.identifier),
+                kind => {
+                    let address =
+                        self.declare(&variable.identifier, kind == DeclarationKind::Const);
+                    self.emit(Instruction::Bind(address));
+                    Ok(())
+                }
+            },
+            LoopTarget::Assignment(target) => self.assign_element(target),
+        };
+        let result = result.and_then(|_| self.loop_body(body));
+        self.scope.pop();
+        let jumps = result?;
+
+        self.emit(Instruction::JumpStatic(start));
+        self.patch(exit);
+        self.close_loop(jumps, start);
+        Ok(())
+    }
+
+    /// Assign the element on top of the stack to a variable or property
+    fn assign_element(&mut self, target: &'a Expr) -> Result<(), CompileError> {
+        // Properties expect their object and key below the value
+        let element = self.allocate();
+        self.emit(Instruction::Bind(element));
+        let place = self.place(target)?;
+        self.emit(Instruction::Load(element));
+        self.store_place(&place)?;
+        self.emit(Instruction::Pop);
+        Ok(())
+    }
+
+    fn c_style_loop(
+        &mut self,
+        prerequisite: Option<&'a Prerequisite>,
+        condition: Option<&'a Expr>,
+        mutation: Option<&'a Expr>,
+        body: &'a FunctionBody,
+    ) -> Result<(), CompileError> {
+        let variables = match prerequisite {
+            Some(Prerequisite::Declaration(variables)) => variables.as_slice(),
+            Some(Prerequisite::Expression(expr)) => {
+                self.expression(expr)?;
+                self.emit(Instruction::Pop);
+                &[]
+            }
+            None => &[],
+        };
+        self.declare_block_scoped(variables)?;
+        for variable in variables {
+            self.declaration(variable)?;
+        }
+
+        let start = self.code.len();
+        let exit = match condition {
+            Some(condition) => {
+                self.expression(condition)?;
+                Some(self.emit(Instruction::JumpConditional(0)))
+            }
+            None => None,
+        };
+        let jumps = self.loop_body(body)?;
+
+        let next = self.code.len();
+        // Closures created by this iteration keep the values they've seen,
+        // the next iteration continues on a copy of every `let` variable
+        for variable in variables {
+            if variable.kind == DeclarationKind::Let {
+                self.load(&variable.identifier)?;
+                self.bind(&variable.identifier)?;
+            }
+        }
+        if let Some(mutation) = mutation {
+            self.expression(mutation)?;
+            self.emit(Instruction::Pop);
+        }
+        self.emit(Instruction::JumpStatic(start));
+
+        if let Some(exit) = exit {
+            self.patch(exit);
+        }
+        self.close_loop(jumps, next);
+        Ok(())
+    }
+
+    fn binary(
+        &mut self,
+        left: &'a Expr,
+        right: &'a Expr,
+        operation: Instruction,
+    ) -> Result<(), CompileError> {
+        self.expression(left)?;
+        self.expression(right)?;
+        self.emit(operation);
+        Ok(())
+    }
+
+    fn expression(&mut self, expr: &'a Expr) -> Result<(), CompileError> {
+        match &expr.kind {
+            ExprKind::Sequence(exprs) => {
+                for (i, expr) in exprs.iter().enumerate() {
+                    if i > 0 {
+                        self.emit(Instruction::Pop);
+                    }
+                    self.expression(expr)?;
+                }
+            }
+            ExprKind::Mutate {
+                target,
+                mutation,
+                assign,
+            } => {
+                let operation = match mutation {
+                    MutationKind::Assign => None,
+                    MutationKind::AddAssign => Some(Instruction::Add),
+                    MutationKind::SubtractAssign => Some(Instruction::Subtract),
+                    MutationKind::ModAssign => Some(Instruction::Mod),
+                    MutationKind::MulAssign => Some(Instruction::Mul),
+                    MutationKind::DivAssign => Some(Instruction::Div),
+                    MutationKind::ExponentAssign => Some(Instruction::Exponent),
+                    MutationKind::LeftShiftAssign => Some(Instruction::LeftShift),
+                    MutationKind::RightShiftAssign => Some(Instruction::RightShift),
+                    MutationKind::UnsignedRightShiftAssign => Some(Instruction::UnsignedRightShift),
+                    MutationKind::BitAndAssign => Some(Instruction::BitAnd),
+                    MutationKind::BitOrAssign => Some(Instruction::BitOr),
+                    MutationKind::BitXorAssign => Some(Instruction::BitXor),
+                    MutationKind::AndAssign => {
+                        return self.logical_assignment(target, assign, &[]);
+                    }
+                    MutationKind::OrAssign => {
+                        return self.logical_assignment(target, assign, &[Instruction::Not]);
+                    }
+                    MutationKind::NullishAssign => {
+                        let nullish = [Instruction::Push(Object::Null), Instruction::Equal];
+                        return self.logical_assignment(target, assign, &nullish);
+                    }
+                };
+
+                let place = self.place(target)?;
+                if let Some(operation) = operation {
+                    self.load_place(&place)?;
+                    self.expression(assign)?;
+                    self.emit(operation);
+                } else {
+                    self.expression(assign)?;
+                }
+                self.store_place(&place)?;
+            }
+            ExprKind::Elvis {
+                condition,
+                case_true,
+                case_false,
+            } => {
+                self.expression(condition)?;
+                let skip_true = self.emit(Instruction::JumpConditional(0));
+                self.expression(case_true)?;
+                let skip_false = self.emit(Instruction::JumpStatic(0));
+                self.patch(skip_true);
+                self.expression(case_false)?;
+                self.patch(skip_false);
+            }
+            // Both `||` and `&&` evaluate to the operand deciding the result,
+            // the right side is only evaluated if needed
+            ExprKind::Or(left, right) => {
+                self.expression(left)?;
+                self.emit(Instruction::Dup);
+                self.emit(Instruction::Not);
+                let end = self.emit(Instruction::JumpConditional(0));
+                self.emit(Instruction::Pop);
+                self.expression(right)?;
+                self.patch(end);
+            }
+            ExprKind::And(left, right) => {
+                self.expression(left)?;
+                self.emit(Instruction::Dup);
+                let end = self.emit(Instruction::JumpConditional(0));
+                self.emit(Instruction::Pop);
+                self.expression(right)?;
+                self.patch(end);
+            }
+            ExprKind::BitOr(left, right) => self.binary(left, right, Instruction::BitOr)?,
+            ExprKind::BitXor(left, right) => self.binary(left, right, Instruction::BitXor)?,
+            ExprKind::BitAnd(left, right) => self.binary(left, right, Instruction::BitAnd)?,
+            ExprKind::Equal(left, right) => self.binary(left, right, Instruction::Equal)?,
+            ExprKind::NotEqual(left, right) => self.binary(left, right, Instruction::NotEqual)?,
+            ExprKind::StrictEqual(left, right) => {
+                self.binary(left, right, Instruction::StrictEqual)?
+            }
+            ExprKind::StrictNotEqual(left, right) => {
+                self.binary(left, right, Instruction::StrictNotEqual)?
+            }
+            ExprKind::SmallerEq(left, right) => {
+                self.binary(left, right, Instruction::SmallerEqual)?
+            }
+            ExprKind::GreaterEq(left, right) => {
+                self.binary(left, right, Instruction::GreaterEqual)?
+            }
+            ExprKind::Smaller(left, right) => self.binary(left, right, Instruction::Smaller)?,
+            ExprKind::Greater(left, right) => self.binary(left, right, Instruction::Greater)?,
+            ExprKind::InstanceOf(left, right) => {
+                self.binary(left, right, Instruction::InstanceOf)?
+            }
+            ExprKind::In(left, right) => self.binary(left, right, Instruction::In)?,
+            ExprKind::LeftShift(left, right) => self.binary(left, right, Instruction::LeftShift)?,
+            ExprKind::RightShift(left, right) => {
+                self.binary(left, right, Instruction::RightShift)?
+            }
+            ExprKind::UnsignedRightShift(left, right) => {
+                self.binary(left, right, Instruction::UnsignedRightShift)?
+            }
+            ExprKind::Add(left, right) => self.binary(left, right, Instruction::Add)?,
+            ExprKind::Sub(left, right) => self.binary(left, right, Instruction::Subtract)?,
+            ExprKind::Div(left, right) => self.binary(left, right, Instruction::Div)?,
+            ExprKind::Mul(left, right) => self.binary(left, right, Instruction::Mul)?,
+            ExprKind::Mod(left, right) => self.binary(left, right, Instruction::Mod)?,
+            ExprKind::Exponent(left, right) => self.binary(left, right, Instruction::Exponent)?,
+            ExprKind::Not(expr) => {
+                self.expression(expr)?;
+                self.emit(Instruction::Not);
+            }
+            ExprKind::Neg(expr) => {
+                self.expression(expr)?;
+                self.emit(Instruction::Negation);
+            }
+            ExprKind::BitNot(expr) => {
+                self.expression(expr)?;
+                self.emit(Instruction::BitNot);
+            }
+            ExprKind::TypeOf(expr) => match &expr.kind {
+                // The only place an undeclared variable may be used
+                ExprKind::Identifier(identifier) if self.scope.get(identifier).is_none() => {
+                    self.emit(Instruction::Push(string("undefined")));
+                }
+                _ => {
+                    self.expression(expr)?;
+                    self.emit(Instruction::TypeOf);
+                }
+            },
+            ExprKind::Plus(expr) => {
+                self.expression(expr)?;
+                self.emit(Instruction::ToNumber);
+            }
+            ExprKind::Void(expr) => {
+                self.expression(expr)?;
+                self.emit(Instruction::Pop);
+                self.emit(Instruction::Push(Object::Undefined));
+            }
+            ExprKind::Delete(expr) => self.delete(expr)?,
+            ExprKind::PreIncrement(target) => {
+                self.increment(target, Instruction::Increment, true)?
+            }
+            ExprKind::PreDecrement(target) => {
+                self.increment(target, Instruction::Decrement, true)?
+            }
+            ExprKind::PostIncrement(target) => {
+                self.increment(target, Instruction::Increment, false)?
+            }
+            ExprKind::PostDecrement(target) => {
+                self.increment(target, Instruction::Decrement, false)?
+            }
+            ExprKind::Nullish(left, right) => {
+                self.expression(left)?;
+                self.emit(Instruction::Dup);
+                self.emit(Instruction::Push(Object::Null));
+                self.emit(Instruction::Equal);
+                let end = self.emit(Instruction::JumpConditional(0));
+                self.emit(Instruction::Pop);
+                self.expression(right)?;
+                self.patch(end);
+            }
+            ExprKind::Member {
+                object,
+                property,
+                optional,
+            } => {
+                self.expression(object)?;
+                if *optional {
+                    self.optional_link();
+                }
+                self.emit(Instruction::Push(string(&property.0)));
+                self.emit(Instruction::Get);
+            }
+            ExprKind::ComputedMember {
+                object,
+                property,
+                optional,
+            } => {
+                self.expression(object)?;
+                if *optional {
+                    self.optional_link();
+                }
+                self.expression(property)?;
+                self.emit(Instruction::Get);
+            }
+            ExprKind::Call {
+                callee,
+                arguments,
+                optional,
+            } => {
+                self.expression(callee)?;
+                if *optional {
+                    self.optional_link();
+                }
+                for argument in arguments {
+                    self.expression(argument)?;
+                }
+                self.emit(Instruction::Jump(arguments.len()));
+            }
+            // The tag is called with the array of strings,
+            // followed by the interpolated values
+            ExprKind::Tag { tag, template } => {
+                self.expression(tag)?;
+                for (text, invalid) in template.strings().zip(&template.invalid) {
+                    let text = if *invalid {
+                        Object::Undefined
+                    } else {
+                        string(text)
+                    };
+                    self.emit(Instruction::Push(text));
+                }
+                self.emit(Instruction::MakeArray(template.end.len() + 1));
+                for (expr, _) in &template.end {
+                    self.expression(expr)?;
+                }
+                self.emit(Instruction::Jump(template.end.len() + 1));
+            }
+            ExprKind::OptionalChain(chain) => {
+                self.optional_chain(|c| c.expression(chain), Object::Undefined)?
+            }
+            ExprKind::Identifier(identifier) => self.load(identifier)?,
+            ExprKind::Value(value) => self.value(value)?,
+        }
+
+        Ok(())
+    }
+
+    /// Prepare the assignment to a variable or a property.
+    /// Properties push their object and key, as expected by `Set`.
+    fn place(&mut self, target: &'a Expr) -> Result<Place<'a>, CompileError> {
+        match &target.kind {
+            ExprKind::Identifier(identifier) => return Ok(Place::Variable(identifier)),
+            ExprKind::Member {
+                object,
+                property,
+                optional,
+            } => {
+                self.expression(object)?;
+                if *optional {
+                    self.optional_link();
+                }
+                self.emit(Instruction::Push(string(&property.0)));
+            }
+            ExprKind::ComputedMember {
+                object,
+                property,
+                optional,
+            } => {
+                self.expression(object)?;
+                if *optional {
+                    self.optional_link();
+                }
+                self.expression(property)?;
+            }
+            _ => unreachable!("only variables and properties are assigned"),
+        }
+
+        Ok(Place::Property)
+    }
+
+    /// Push the current value of `place`, keeping the object and key of properties
+    fn load_place(&mut self, place: &Place) -> Result<(), CompileError> {
+        match place {
+            Place::Variable(identifier) => self.load(identifier)?,
+            Place::Property => {
+                self.emit(Instruction::Dup2);
+                self.emit(Instruction::Get);
+            }
+        }
+        Ok(())
+    }
+
+    /// Assign the value on top to `place`, leaving the value on the stack
+    fn store_place(&mut self, place: &Place) -> Result<(), CompileError> {
+        match place {
+            Place::Variable(identifier) => {
+                self.emit(Instruction::Dup);
+                self.store(identifier)?;
+            }
+            Place::Property => {
+                self.emit(Instruction::Set);
+            }
+        }
+        Ok(())
+    }
+
+    /// Assignments like `x ??= y`, evaluating and assigning `y` only
+    /// if `test` turns the current value into something truthy
+    fn logical_assignment(
+        &mut self,
+        target: &'a Expr,
+        assign: &'a Expr,
+        test: &[Instruction],
+    ) -> Result<(), CompileError> {
+        let place = self.place(target)?;
+        self.load_place(&place)?;
+        self.emit(Instruction::Dup);
+        for instruction in test {
+            self.emit(instruction.clone());
+        }
+        let keep = self.emit(Instruction::JumpConditional(0));
+        self.emit(Instruction::Pop);
+        self.expression(assign)?;
+        self.store_place(&place)?;
+
+        if let Place::Property = place {
+            let end = self.emit(Instruction::JumpStatic(0));
+            self.patch(keep);
+            // Drop the object and key below the current value
+            let value = self.allocate();
+            self.emit(Instruction::Store(value));
+            self.emit(Instruction::Pop);
+            self.emit(Instruction::Pop);
+            self.emit(Instruction::Load(value));
+            self.patch(end);
+        } else {
+            self.patch(keep);
+        }
+        Ok(())
+    }
+
+    /// Leave the surrounding optional chain, if the value on top is `null` or `undefined`
+    fn optional_link(&mut self) {
+        self.emit(Instruction::Dup);
+        self.emit(Instruction::Push(Object::Null));
+        self.emit(Instruction::NotEqual);
+        let exit = self.emit(Instruction::JumpConditional(0));
+        self.function()
+            .chains
+            .last_mut()
+            .expect("optional links are part of a chain")
+            .push(exit);
+    }
+
+    /// `delete` removes properties.
+    /// Variables can't be deleted, any other value is evaluated and ignored.
+    fn delete(&mut self, expr: &'a Expr) -> Result<(), CompileError> {
+        match &expr.kind {
+            ExprKind::Identifier(_) => {
+                self.emit(Instruction::Push(Object::Boolean(false)));
+            }
+            ExprKind::Member { .. } | ExprKind::ComputedMember { .. } => {
+                self.place(expr)?;
+                self.emit(Instruction::Delete);
+            }
+            ExprKind::OptionalChain(chain) => {
+                self.optional_chain(|c| c.delete(chain), Object::Boolean(true))?
+            }
+            _ => {
+                self.expression(expr)?;
+                self.emit(Instruction::Pop);
+                self.emit(Instruction::Push(Object::Boolean(true)));
+            }
+        }
+        Ok(())
+    }
+
+    /// Compile the links of an optional chain using `compile`.
+    /// Once an optional link finds `null` or `undefined`, the chain results in `skipped`.
+    fn optional_chain(
+        &mut self,
+        compile: impl FnOnce(&mut Self) -> Result<(), CompileError>,
+        skipped: Object,
+    ) -> Result<(), CompileError> {
+        self.function().chains.push(Vec::new());
+        let result = compile(self);
+        let exits = self.function().chains.pop().expect("chain pushed above");
+        result?;
+
+        let end = self.emit(Instruction::JumpStatic(0));
+        for exit in exits {
+            self.patch(exit);
+        }
+        self.emit(Instruction::Pop);
+        self.emit(Instruction::Push(skipped));
+        self.patch(end);
+        Ok(())
+    }
+
+    /// `++x` and `--x` evaluate to the value after the mutation,
+    /// `x++` and `x--` to the value before
+    fn increment(
+        &mut self,
+        target: &'a Expr,
+        operation: Instruction,
+        prefix: bool,
+    ) -> Result<(), CompileError> {
+        // The operand is converted first, `"5"++` counts up instead of concatenating
+        let place = self.place(target)?;
+        if prefix {
+            self.load_place(&place)?;
+            self.emit(Instruction::ToNumeric);
+            self.emit(operation);
+            return self.store_place(&place);
+        }
+
+        match place {
+            Place::Variable(identifier) => {
+                self.load(identifier)?;
+                self.emit(Instruction::ToNumeric);
+                self.emit(Instruction::Dup);
+                self.emit(operation);
+                self.store(identifier)?;
+            }
+            Place::Property => {
+                let previous = self.allocate();
+                self.emit(Instruction::Dup2);
+                self.emit(Instruction::Get);
+                self.emit(Instruction::ToNumeric);
+                self.emit(Instruction::Dup);
+                self.emit(Instruction::Store(previous));
+                self.emit(operation);
+                self.emit(Instruction::Set);
+                self.emit(Instruction::Pop);
+                self.emit(Instruction::Load(previous));
+            }
+        }
+        Ok(())
+    }
+
+    fn value(&mut self, value: &'a obj::Object) -> Result<(), CompileError> {
+        match &value.kind {
+            ObjectKind::Boolean(b) => {
+                self.emit(Instruction::Push(Object::Boolean(*b)));
+            }
+            ObjectKind::Null => {
+                self.emit(Instruction::Push(Object::Null));
+            }
+            ObjectKind::Undefined => {
+                self.emit(Instruction::Push(Object::Undefined));
+            }
+            ObjectKind::Number(n) => {
+                self.emit(Instruction::Push(Object::Number(*n)));
+            }
+            ObjectKind::BigInt(n) => {
+                self.emit(Instruction::Push(Object::BigInt(*n)));
+            }
+            ObjectKind::String(template) => {
+                // The leading string turns every addition into a concatenation
+                self.emit(Instruction::Push(string(&template.start)));
+                for (expr, text) in &template.end {
+                    self.expression(expr)?;
+                    self.emit(Instruction::Add);
+                    self.emit(Instruction::Push(string(text)));
+                    self.emit(Instruction::Add);
+                }
+            }
+            ObjectKind::Array(elements) => {
+                for element in elements {
+                    self.expression(element)?;
+                }
+                self.emit(Instruction::MakeArray(elements.len()));
+            }
+            ObjectKind::Map(map) => {
+                for (key, value) in map {
+                    self.emit(Instruction::Push(string(&key.0)));
+                    self.expression(value)?;
+                }
+                self.emit(Instruction::MakeObject(map.len()));
+            }
+            ObjectKind::Closure { args, body } => {
+                self.closure(|compiler| compiler.arrow_body(args, body))?
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn string(s: &str) -> Object {
+    Object::String(Rc::new(s.to_string()))
+}
+
+/// Value of a literal, which is known without running any code
+fn constant(expr: &Expr) -> Option<Object> {
+    let value = match &expr.kind {
+        ExprKind::Value(value) => value,
+        _ => return None,
+    };
+
+    Some(match &value.kind {
+        ObjectKind::Null => Object::Null,
+        ObjectKind::Undefined => Object::Undefined,
+        ObjectKind::Boolean(b) => Object::Boolean(*b),
+        ObjectKind::Number(n) => Object::Number(*n),
+        ObjectKind::BigInt(n) => Object::BigInt(*n),
+        ObjectKind::String(template) if template.end.is_empty() => string(&template.start),
+        _ => return None,
+    })
+}
+
+/// Collect the names of all `var` declarations in `body`,
+/// including nested blocks, but not nested functions
+fn var_names<'a>(body: &'a FunctionBody, names: &mut Vec<&'a Identifier>) {
+    fn add<'a>(variables: &'a [Variable], names: &mut Vec<&'a Identifier>) {
+        for variable in variables {
+            if variable.kind == DeclarationKind::Var && !names.contains(&&variable.identifier) {
+                names.push(&variable.identifier);
+            }
+        }
+    }
+
+    for mut statement in &body.instructions {
+        while let StatementKind::Labeled { body, .. } = &statement.kind {
+            statement = body;
+        }
+
+        match &statement.kind {
+            StatementKind::Declaration(variables) => add(variables, names),
+            StatementKind::Block(body) => var_names(body, names),
+            StatementKind::Try {
+                body,
+                catch,
+                finally,
+            } => {
+                var_names(body, names);
+                if let Some(catch) = catch {
+                    var_names(&catch.body, names);
+                }
+                if let Some(finally) = finally {
+                    var_names(finally, names);
+                }
+            }
+            StatementKind::If {
+                body, else_branch, ..
+            } => {
+                var_names(body, names);
+                if let Some(else_branch) = else_branch {
+                    var_names(else_branch, names);
+                }
+            }
+            StatementKind::While { body, .. } | StatementKind::DoWhile { body, .. } => {
+                var_names(body, names)
+            }
+            StatementKind::Switch(switch) => {
+                for case in &switch.cases {
+                    var_names(&case.body, names);
+                }
+            }
+            StatementKind::For(for_loop) => {
+                match &for_loop.condition {
+                    ForLoopCondition::CStyle {
+                        prerequisite: Some(Prerequisite::Declaration(variables)),
+                        ..
+                    } => add(variables, names),
+                    ForLoopCondition::CStyle { .. } => {}
+                    ForLoopCondition::ElemOfIter {
+                        element: LoopTarget::Declaration(variable),
+                        ..
+                    }
+                    | ForLoopCondition::KeyInIter {
+                        key: LoopTarget::Declaration(variable),
+                        ..
+                    } => add(std::slice::from_ref(variable), names),
+                    ForLoopCondition::ElemOfIter { .. } | ForLoopCondition::KeyInIter { .. } => {}
+                }
+                var_names(&for_loop.body, names);
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse::parse;
+    use Instruction::*;
+
+    fn compile(source: &str) -> Result<Vec<Instruction>, CompileError> {
+        let (rest, ast) = parse(source).unwrap();
+        assert_eq!("", rest.trim());
+        generate_code(&ast)
+    }
+
+    #[test]
+    fn declaration() {
+        assert_eq!(
+            compile("let x = 1 + 2").unwrap(),
+            vec![
+                Enter {
+                    arguments: 0,
+                    locals: 1
+                },
+                Push(Object::Number(1.0)),
+                Push(Object::Number(2.0)),
+                Add,
+                Bind(0),
+                Push(Object::Undefined),
+                Return,
+            ]
+        );
+    }
+
+    #[test]
+    fn while_loop() {
+        assert_eq!(
+            compile("while (true) break").unwrap(),
+            vec![
+                Enter {
+                    arguments: 0,
+                    locals: 0
+                },
+                Push(Object::Boolean(true)),
+                JumpConditional(5),
+                JumpStatic(5),
+                JumpStatic(1),
+                Push(Object::Undefined),
+                Return,
+            ]
+        );
+    }
+
+    #[test]
+    fn jump_table() {
+        let code = compile("switch (1) { case 1: default: break; case 'two': }").unwrap();
+        assert_eq!(
+            code[2],
+            JumpTable {
+                cases: vec![(Object::Number(1.0), 3), (string("two"), 4)],
+                default: 3
+            }
+        );
+        assert_eq!(code[3], JumpStatic(4));
+
+        let code = compile("let x = 1\nswitch (x) { case x: }").unwrap();
+        assert!(!code.iter().any(|i| matches!(i, JumpTable { .. })));
+        assert!(code.contains(&StrictNotEqual));
+    }
+
+    #[test]
+    fn global_access() {
+        let code = compile(
+            "
+            let counter = 0
+            function count() {
+                counter += 1
+            }
+            ",
+        )
+        .unwrap();
+
+        assert!(code.contains(&LoadGlobal(0)));
+        assert!(code.contains(&StoreGlobal(0)));
+    }
+
+    #[test]
+    fn captures() {
+        let code = compile(
+            "
+            function outer(x) {
+                return () => () => x
+            }
+            ",
+        )
+        .unwrap();
+
+        assert!(code.contains(&Closure {
+            function: 4,
+            captures: vec![Capture::Local(0)]
+        }));
+        assert!(code.contains(&LoadCaptured(0)));
+        assert!(code.contains(&Closure {
+            function: 6,
+            captures: vec![Capture::Captured(0)]
+        }));
+    }
+
+    #[test]
+    fn undefined_variable() {
+        assert_eq!(
+            compile("x = 1"),
+            Err(CompileError::UndefinedVariable(Identifier("x".to_string())))
+        );
+        // `typeof` only tolerates the variable itself
+        assert!(compile("typeof x").is_ok());
+        assert_eq!(
+            compile("typeof x.y"),
+            Err(CompileError::UndefinedVariable(Identifier("x".to_string())))
+        );
+    }
+
+    #[test]
+    fn illegal_break() {
+        assert_eq!(compile("break"), Err(CompileError::IllegalBreak));
+        assert_eq!(compile("continue"), Err(CompileError::IllegalContinue));
+        assert_eq!(
+            compile("switch (1) { case 1: continue }"),
+            Err(CompileError::IllegalContinue)
+        );
+        assert!(compile("while (true) switch (1) { case 1: continue }").is_ok());
+    }
+
+    #[test]
+    fn labels() {
+        let undefined = Err(CompileError::UndefinedLabel(Identifier(
+            "outer".to_string(),
+        )));
+        assert_eq!(compile("while (true) break outer"), undefined);
+        assert_eq!(
+            compile("outer: while (true) {}\nwhile (true) continue outer"),
+            undefined
+        );
+        assert_eq!(
+            compile("function f() { outer: while (true) () => { break outer } }"),
+            undefined
+        );
+        assert_eq!(
+            compile("outer: { while (true) continue outer }"),
+            Err(CompileError::IllegalContinue)
+        );
+        assert_eq!(compile("block: { break }"), Err(CompileError::IllegalBreak));
+        assert!(compile("a: b: while (true) { continue a; break b }").is_ok());
+    }
+
+    #[test]
+    fn const_assignment() {
+        let error = Err(CompileError::ConstAssignment(Identifier("x".to_string())));
+        assert_eq!(compile("const x = 1\nx = 2"), error);
+        assert_eq!(compile("const x = 1\nfunction f() { x++ }"), error);
+        assert!(compile("const x = 1\nfunction f() { let x = 1\nx = 2 }").is_ok());
+    }
+
+    #[test]
+    fn redeclaration() {
+        let error = Err(CompileError::Redeclaration(Identifier("x".to_string())));
+        assert_eq!(compile("let x = 1; let x = 2"), error);
+        assert_eq!(compile("let x = 1\nconst x = 2"), error);
+        assert_eq!(compile("for (let x = 0, x = 1;;) {}"), error);
+        assert_eq!(
+            compile("switch (1) { case 1: let x = 1; break\ncase 2: const x = 2 }"),
+            error
+        );
+        assert_eq!(compile("let x; var x = 1"), error);
+        assert_eq!(compile("var x = 1; let x"), error);
+        assert_eq!(compile("let x\nif (true) { var x }"), error);
+        assert_eq!(compile("function f() { const x = 1; { var x } }"), error);
+        let error = Err(CompileError::Redeclaration(Identifier("f".to_string())));
+        assert_eq!(compile("let f; function f() {}"), error);
+        assert_eq!(compile("function f() {}\nconst f = 1"), error);
+        assert_eq!(compile("{ function f() {}\nlet f }"), error);
+
+        assert!(compile("var x; var x").is_ok());
+        assert!(compile("function f() {}\nfunction f() {}").is_ok());
+        assert!(compile("let x\nfunction f() { var x }").is_ok());
+        assert!(compile("{ let x }\nvar x").is_ok());
+        assert!(compile("let x = 1\n{ let x = 2 }").is_ok());
+        assert!(compile("let x = 1\nfunction f() { let x = 2 }").is_ok());
+        assert!(compile("switch (1) { case 1: { let x = 1 } case 2: { let x = 2 } }").is_ok());
+    }
+
+    #[test]
+    fn var_hoisting() {
+        let code = compile(
+            "
+            function f(a) {
+                if (a) { var x = 1, a = 2 }
+                return x
+            }
+            ",
+        )
+        .unwrap();
+
+        // `x` is bound on entry, `a` is the argument
+        assert_eq!(
+            &code[2..6],
+            &[
+                Enter {
+                    arguments: 1,
+                    locals: 1
+                },
+                Push(Object::Undefined),
+                Bind(1),
+                Load(0),
+            ]
+        );
+    }
+
+    #[test]
+    fn hoisting() {
+        let code = compile(
+            "
+            let x = square(2)
+            function square(x) { return x*x }
+            ",
+        );
 
-        },
-    )
+        assert!(code.is_ok());
+    }
 }
@@ -1,47 +1,73 @@
 use std::collections::HashMap;
 
-pub struct Scope<'a, K, V>
+/// Nested lexical scopes, the innermost scope shadows all outer ones
+pub struct Scope<K, V>
 where
-    K: std::cmp::Eq + std::hash::Hash
+    K: std::cmp::Eq + std::hash::Hash,
 {
-    parent: Option<&'a Scope<'a,K, V>>,
-    map: HashMap<K, V>
+    layers: Vec<HashMap<K, V>>,
 }
 
-impl<'a, K, V> Scope<'a, K, V>
+impl<K, V> Scope<K, V>
 where
-    K: std::cmp::Eq + std::hash::Hash
+    K: std::cmp::Eq + std::hash::Hash,
 {
-    pub fn get_mut(&mut self, key: &K) -> Option<&mut V> {
-        match self.map.get_mut(key) {
-            some => some,
-            None => if let Some(scope) = self.parent {
-                scope.get_mut(key)
-            } else {
-                None
-            }
-        }
+    pub fn new() -> Scope<K, V> {
+        Scope { layers: Vec::new() }
+    }
+
+    /// Open a new, innermost scope
+    pub fn push(&mut self) {
+        self.layers.push(HashMap::new());
+    }
+
+    /// Close the innermost scope, dropping all of it's definitions
+    pub fn pop(&mut self) {
+        self.layers.pop();
+    }
+
+    /// Define `key` in the innermost scope
+    pub fn insert(&mut self, key: K, value: V) {
+        self.layers
+            .last_mut()
+            .expect("no scope opened")
+            .insert(key, value);
+    }
+
+    /// Number of scopes currently opened
+    pub fn depth(&self) -> usize {
+        self.layers.len()
+    }
+
+    /// Whether `key` is defined in the innermost scope, not just visible
+    pub fn defines(&self, key: &K) -> bool {
+        self.layers.last().is_some_and(|map| map.contains_key(key))
     }
 
     pub fn get(&self, key: &K) -> Option<&V> {
-        match self.map.get(key) {
-            some => some,
-            None => if let Some(scope) = self.parent {
-                scope.get(key)
-            } else {
-                None
-            }
-        }
-    }
-
-    pub fn has(&self, key: &K) -> bool {
-        match self.map.get(key) {
-            Some(_) => true,
-            None => if let Some(scope) = self.parent {
-                scope.has(key)
-            } else {
-                false
-            }
-        }
+        self.layers.iter().rev().find_map(|map| map.get(key))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Scope;
+
+    #[test]
+    fn shadowing() {
+        let mut scope = Scope::new();
+        scope.push();
+        scope.insert("x", 1);
+        scope.push();
+        scope.insert("x", 2);
+        assert_eq!(Some(&2), scope.get(&"x"));
+        assert!(scope.defines(&"x"));
+        scope.pop();
+        scope.push();
+        assert!(!scope.defines(&"x"));
+        scope.pop();
+        assert_eq!(Some(&1), scope.get(&"x"));
+        scope.pop();
+        assert_eq!(None, scope.get(&"x"));
     }
 }
@@ -1,7 +1,7 @@
 extern crate gc;
 extern crate gc_derive;
 
-mod compile;
+pub mod compile;
 pub mod parse;
-pub use parse::parse;
-mod vm;
+pub use parse::{parse, parse_complete, parse_recovering};
+pub mod vm;
@@ -0,0 +1,133 @@
+use crate::parse::{
+    span::{LineIndex, Locate, Span},
+    whitespace, FlagGuard,
+};
+use nom::IResult;
+use std::cell::Cell;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CommentKind {
+    /// `// ...` up to the end of the line
+    Line,
+    /// `/* ... */`
+    Block,
+}
+
+/// Comment in the source code, only kept on request
+#[derive(Debug, Clone, PartialEq)]
+pub struct Comment {
+    pub kind: CommentKind,
+    /// Text of the comment, without the delimiters
+    pub text: String,
+    pub span: Span,
+}
+
+thread_local! {
+    static KEEPING: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Run `f` with comments being attached to the Ast
+pub fn keeping<T>(f: impl FnOnce() -> T) -> T {
+    let _keeping = FlagGuard::set(&KEEPING, true);
+    f()
+}
+
+/// Recognize a single comment, returning it's kind and text.
+/// An unterminated block comment is not recognized.
+pub fn recognize(input: &str) -> Option<(&str, CommentKind, &str)> {
+    if let Some(comment) = input.strip_prefix("//") {
+        let end = comment.find('\n').unwrap_or(comment.len());
+        let text = comment[..end].trim_end_matches('\r');
+        Some((&comment[end..], CommentKind::Line, text))
+    } else if let Some(comment) = input.strip_prefix("/*") {
+        let end = comment.find("*/")?;
+        Some((&comment[end + 2..], CommentKind::Block, &comment[..end]))
+    } else {
+        None
+    }
+}
+
+/// Skip whitespace and comments,
+/// returning the comments, if they are to be kept.
+/// Will always suceed
+pub fn leading(input: &str) -> IResult<&str, Vec<Comment>> {
+    let (rest, trivia) = whitespace(input)?;
+    if !KEEPING.with(Cell::get) {
+        return Ok((rest, Vec::new()));
+    }
+
+    // Remaining input, starting at a suffix of `trivia`
+    let at = |suffix: &str| &input[trivia.len() - suffix.len()..];
+
+    let mut comments = Vec::new();
+    let mut trivia = trivia;
+    while !trivia.is_empty() {
+        let (_, start) = whitespace_only(trivia);
+        match recognize(start) {
+            Some((after, kind, text)) => {
+                comments.push(Comment {
+                    kind,
+                    text: text.to_string(),
+                    span: Span::between(at(start), at(after)),
+                });
+                trivia = after;
+            }
+            None => break,
+        }
+    }
+
+    Ok((rest, comments))
+}
+
+/// Split off spaces, tabs and newlines
+fn whitespace_only(input: &str) -> (&str, &str) {
+    let rest = input.trim_start_matches([' ', '\n', '\r', '\t']);
+    (&input[..input.len() - rest.len()], rest)
+}
+
+impl Locate for Comment {
+    fn locate(&mut self, lines: &LineIndex) {
+        self.span.locate(lines);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeping_after_panic() {
+        let result = std::panic::catch_unwind(|| keeping(|| panic!("parser bug")));
+        assert!(result.is_err());
+        assert!(!KEEPING.with(Cell::get));
+    }
+
+    #[test]
+    fn recognize_comments() {
+        assert_eq!(
+            Some(("\nx", CommentKind::Line, " hello")),
+            recognize("// hello\nx")
+        );
+        assert_eq!(
+            Some((" x", CommentKind::Block, " a\nb ")),
+            recognize("/* a\nb */ x")
+        );
+        assert_eq!(None, recognize("/* open"));
+        assert_eq!(None, recognize("/ 2"));
+    }
+
+    #[test]
+    fn leading_comments() {
+        let input = "  // first\n  /* second */ x";
+        assert_eq!(Ok(("x", Vec::new())), leading(input));
+
+        let (rest, comments) = keeping(|| leading(input)).unwrap();
+        assert_eq!("x", rest);
+        let texts: Vec<_> = comments.iter().map(|c| c.text.as_str()).collect();
+        assert_eq!(vec![" first", " second "], texts);
+        assert_eq!(
+            (input.len() - 13, input.len() - 25),
+            (comments[1].span.start.offset, comments[1].span.end.offset)
+        );
+    }
+}
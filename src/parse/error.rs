@@ -0,0 +1,594 @@
+use crate::parse::comment;
+use crate::parse::identifier::is_id_continue;
+use crate::parse::span::{LineIndex, Locate, Position, Span};
+use crate::parse::{whitespace, FlagGuard};
+use nom::IResult;
+use std::cell::{Cell, RefCell};
+use std::fmt;
+
+/// Something the parser was looking for
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Expected {
+    Token(&'static str),
+    Char(char),
+    /// Name of a whole construct, like `expression`
+    Label(&'static str),
+}
+
+impl fmt::Display for Expected {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Expected::Token(token) => write!(f, "`{}`", token),
+            // A backtick can't be quoted by backticks
+            Expected::Char('`') => write!(f, "'`'"),
+            Expected::Char(c) => write!(f, "`{}`", c),
+            Expected::Label(label) => write!(f, "{}", label),
+        }
+    }
+}
+
+/// Failure furthest into the input, the most likely place of a mistake
+#[derive(Debug, Clone)]
+struct Failure {
+    /// Length of the remaining input
+    remaining: usize,
+    expected: Vec<Expected>,
+}
+
+thread_local! {
+    static FURTHEST: RefCell<Option<Failure>> = const { RefCell::new(None) };
+    static RECOVERING: Cell<bool> = const { Cell::new(false) };
+}
+
+fn merge(a: Option<Failure>, b: Option<Failure>) -> Option<Failure> {
+    match (a, b) {
+        (Some(mut a), Some(b)) => {
+            if a.remaining == b.remaining {
+                for expected in b.expected {
+                    if !a.expected.contains(&expected) {
+                        a.expected.push(expected);
+                    }
+                }
+                Some(a)
+            } else if a.remaining < b.remaining {
+                Some(a)
+            } else {
+                Some(b)
+            }
+        }
+        (a, None) => a,
+        (None, b) => b,
+    }
+}
+
+/// Note, that a parser failed on `input`, while looking for `expected`
+pub fn expected(input: &str, expected: Expected) {
+    FURTHEST.with(|furthest| {
+        let failure = Failure {
+            remaining: input.len(),
+            expected: vec![expected],
+        };
+        let previous = furthest.borrow_mut().take();
+        *furthest.borrow_mut() = merge(previous, Some(failure));
+    })
+}
+
+/// Note a failure on `input`, which is certain enough
+/// to replace everything else expected at the same place.
+/// The parser has to return `nom::Err::Failure`, so that no alternative is tried.
+pub fn failed(input: &str, expected: Expected) {
+    FURTHEST.with(|furthest| {
+        let mut furthest = furthest.borrow_mut();
+        if furthest
+            .as_ref()
+            .is_none_or(|failure| failure.remaining >= input.len())
+        {
+            *furthest = Some(Failure {
+                remaining: input.len(),
+                expected: vec![expected],
+            });
+        }
+    })
+}
+
+/// Run `f`, handing out the failures noted in the meantime
+fn isolated<T>(f: impl FnOnce() -> T) -> (T, Option<Failure>) {
+    let mut isolation = Isolation {
+        saved: Some(FURTHEST.with(|furthest| furthest.borrow_mut().take())),
+    };
+    let result = f();
+    let saved = isolation.saved.take().unwrap_or_default();
+    let noted = FURTHEST.with(|furthest| furthest.replace(saved));
+    (result, noted)
+}
+
+/// Failures noted outside of `isolated`,
+/// which are put back in place, if `f` panics
+struct Isolation {
+    saved: Option<Option<Failure>>,
+}
+
+impl Drop for Isolation {
+    fn drop(&mut self) {
+        if let Some(saved) = self.saved.take() {
+            FURTHEST.with(|furthest| furthest.replace(saved));
+        }
+    }
+}
+
+fn note(failure: Option<Failure>) {
+    FURTHEST.with(|furthest| {
+        let previous = furthest.borrow_mut().take();
+        *furthest.borrow_mut() = merge(previous, failure);
+    })
+}
+
+/// Wrap around a Parser to report it as `label`,
+/// in case it fails without getting past the start of the input.
+/// Failures, as opposed to errors, keep their own more specific expectation.
+pub fn label<'a, T>(
+    label: &'static str,
+    f: impl Fn(&'a str) -> IResult<&'a str, T>,
+) -> impl Fn(&'a str) -> IResult<&'a str, T> {
+    move |input: &str| {
+        let (result, noted) = isolated(|| f(input));
+
+        // Alternatives not taken at the start are no longer of interest after a success
+        match noted {
+            Some(failure)
+                if failure.remaining < input.len()
+                    || matches!(result, Err(nom::Err::Failure(_))) =>
+            {
+                note(Some(failure))
+            }
+            _ if result.is_err() => expected(input, Expected::Label(label)),
+            _ => {}
+        }
+
+        result
+    }
+}
+
+/// Wrap around a Parser, to not note any of it's failures.
+/// Used for optional continuations, like binary operators.
+pub fn quiet<'a, T>(
+    f: impl Fn(&'a str) -> IResult<&'a str, T>,
+) -> impl Fn(&'a str) -> IResult<&'a str, T> {
+    move |input: &str| isolated(|| f(input)).0
+}
+
+/// Forget all failures noted so far
+pub fn reset() {
+    FURTHEST.with(|furthest| *furthest.borrow_mut() = None);
+}
+
+/// Run `f` with recovery enabled,
+/// so that function bodies keep going after a broken statement.
+pub fn recovering<T>(f: impl FnOnce() -> T) -> T {
+    let _recovering = FlagGuard::set(&RECOVERING, true);
+    f()
+}
+
+/// Parse `item` as often as possible, like `many0`.
+///
+/// While recovering, an item failing is skipped up to the next statement boundary,
+/// and replaced by whatever `on_error` makes of the error.
+/// Stops at a closing `}` or the next clause of a switch,
+/// which belong to the surrounding block.
+pub fn many_recovering<'a, T>(
+    item: impl Fn(&'a str) -> IResult<&'a str, T>,
+    on_error: impl Fn(SyntaxError, Span) -> T,
+) -> impl Fn(&'a str) -> IResult<&'a str, Vec<T>> {
+    move |input: &'a str| {
+        if !RECOVERING.with(Cell::get) {
+            return nom::multi::many0(&item)(input);
+        }
+
+        let mut items = Vec::new();
+        let mut input = input;
+        loop {
+            let (start, _) = whitespace(input)?;
+            if start.is_empty() || start.starts_with('}') || clause(start).is_some() {
+                return Ok((input, items));
+            }
+
+            let (result, failure) = isolated(|| item(input));
+            note(failure);
+
+            input = match result {
+                Ok((rest, item)) => {
+                    items.push(item);
+                    rest
+                }
+                Err(_) => {
+                    // Previous items might have failed further in, on optional parts
+                    let noted = FURTHEST.with(|furthest| furthest.borrow().clone());
+                    let error = SyntaxError::furthest(start, noted);
+                    let rest = synchronize(&start[start.len() - error.position.offset..]);
+                    items.push(on_error(error, Span::between(start, rest)));
+                    rest
+                }
+            };
+        }
+    }
+}
+
+/// Input after a `case` or `default` label starting `input`
+pub fn clause(input: &str) -> Option<&str> {
+    ["case", "default"].iter().find_map(|label| {
+        input
+            .strip_prefix(label)
+            .filter(|rest| !rest.starts_with(|c: char| is_id_continue(c) || c == '\\'))
+    })
+}
+
+/// Skip to the next statement boundary,
+/// which is a line break, a closing `}` or right after a `;`.
+/// Brackets, strings and comments are skipped as a whole.
+fn synchronize(input: &str) -> &str {
+    let mut depth = 0;
+    let mut quote = None;
+    let mut escaped = false;
+    let mut skip_to = 0;
+
+    for (i, c) in input.char_indices() {
+        if i < skip_to {
+            continue;
+        }
+
+        if let Some(q) = quote {
+            if escaped {
+                escaped = false;
+            } else if c == '\\' {
+                escaped = true;
+            } else if c == q {
+                quote = None;
+            } else if c == '\n' && q != '`' {
+                // Unterminated string
+                quote = None;
+                if depth == 0 {
+                    return &input[i..];
+                }
+            }
+            continue;
+        }
+
+        if let Some((after, _, _)) = comment::recognize(&input[i..]) {
+            skip_to = input.len() - after.len();
+            continue;
+        }
+
+        match c {
+            '"' | '\'' | '`' => quote = Some(c),
+            '(' | '[' | '{' => depth += 1,
+            ')' | ']' | '}' if depth > 0 => depth -= 1,
+            '\n' | '}' if depth == 0 => return &input[i..],
+            ';' if depth == 0 => return &input[i + 1..],
+            _ => {}
+        }
+    }
+
+    ""
+}
+
+/// Syntax error, pointing to the place the parser got stuck.
+///
+/// Like spans, errors are unlocated while parsing,
+/// with `position.offset` counting from the end of the input.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SyntaxError {
+    pub position: Position,
+    pub expected: Vec<Expected>,
+    /// Token found instead, `None` at the end of input
+    pub found: Option<String>,
+    /// Line of the source code, the error is located in
+    pub line: String,
+}
+
+impl SyntaxError {
+    /// Error at `failure`, for a parser that got stuck on `input`.
+    /// Failures before `input` are ignored.
+    fn furthest(input: &str, failure: Option<Failure>) -> SyntaxError {
+        let failure = failure
+            .filter(|failure| failure.remaining <= input.len())
+            .unwrap_or(Failure {
+                remaining: input.len(),
+                expected: Vec::new(),
+            });
+
+        let remaining = &input[input.len() - failure.remaining..];
+        let found = remaining.chars().next().map(|c| {
+            if is_id_continue(c) {
+                remaining
+                    .chars()
+                    .take_while(|c| is_id_continue(*c))
+                    .collect()
+            } else {
+                c.to_string()
+            }
+        });
+
+        SyntaxError {
+            position: Position {
+                offset: failure.remaining,
+                line: 0,
+                column: 0,
+            },
+            expected: failure.expected,
+            found,
+            line: String::new(),
+        }
+    }
+
+    /// Error right at the start of `input`
+    pub fn unexpected(input: &str, expected: Expected) -> SyntaxError {
+        let failure = Failure {
+            remaining: input.len(),
+            expected: vec![expected],
+        };
+        SyntaxError::furthest(input, Some(failure))
+    }
+
+    /// Error at the furthest failure noted, but at least at `rest`
+    pub fn at_furthest(source: &str, rest: &str) -> SyntaxError {
+        let noted = FURTHEST.with(|furthest| furthest.borrow().clone());
+        let mut error = SyntaxError::furthest(rest, noted);
+        error.locate(&LineIndex::new(source));
+        error
+    }
+}
+
+impl Locate for SyntaxError {
+    fn locate(&mut self, lines: &LineIndex) {
+        if self.position.line == 0 {
+            self.position = lines.position(self.position.offset);
+            self.line = lines.line(self.position.line).to_string();
+        }
+    }
+}
+
+/// Nodes, which might contain syntax errors
+pub trait CollectErrors {
+    fn collect_errors(&self, errors: &mut Vec<SyntaxError>);
+}
+
+impl<T: CollectErrors> CollectErrors for Box<T> {
+    fn collect_errors(&self, errors: &mut Vec<SyntaxError>) {
+        self.as_ref().collect_errors(errors)
+    }
+}
+
+impl<T: CollectErrors> CollectErrors for Option<T> {
+    fn collect_errors(&self, errors: &mut Vec<SyntaxError>) {
+        if let Some(node) = self {
+            node.collect_errors(errors)
+        }
+    }
+}
+
+impl<T: CollectErrors> CollectErrors for Vec<T> {
+    fn collect_errors(&self, errors: &mut Vec<SyntaxError>) {
+        for node in self {
+            node.collect_errors(errors)
+        }
+    }
+}
+
+impl fmt::Display for SyntaxError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "expected ")?;
+        match self.expected.split_last() {
+            None => write!(f, "end of input")?,
+            Some((last, [])) => write!(f, "{}", last)?,
+            Some((last, others)) => {
+                let others: Vec<String> = others.iter().map(|e| e.to_string()).collect();
+                write!(f, "{} or {}", others.join(", "), last)?
+            }
+        }
+
+        // The message stays on one line
+        match &self.found {
+            Some(found) if found.starts_with(['\n', '\r', '\u{2028}', '\u{2029}']) => {
+                write!(f, ", found line break")?
+            }
+            Some(found) => write!(f, ", found `{}`", found)?,
+            None => write!(f, ", found end of input")?,
+        }
+        writeln!(f, " at {}:{}", self.position.line, self.position.column)?;
+
+        let number = self.position.line.to_string();
+        let margin = " ".repeat(number.len());
+        // Keep tabs, so that the caret lines up
+        let indent: String = self
+            .line
+            .chars()
+            .take(self.position.column.saturating_sub(1))
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+        let width = self.found.as_ref().map_or(1, |found| found.chars().count());
+        let underline = "^".repeat(width);
+
+        writeln!(f, "{} |", margin)?;
+        writeln!(f, "{} | {}", number, self.line)?;
+        write!(f, "{} | {}{}", margin, indent, underline)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{synchronize, Expected};
+    use crate::parse::instruction::StatementKind;
+    use crate::parse::{parse_complete, parse_recovering};
+
+    #[test]
+    fn complete() {
+        assert!(parse_complete("let x = 1\n").is_ok());
+        assert!(parse_complete("").is_ok());
+    }
+
+    #[test]
+    fn missing_parenthesis() {
+        let error = parse_complete("let x = (1 + 2").unwrap_err();
+        assert_eq!(vec![Expected::Char(')')], error.expected);
+        assert_eq!(None, error.found);
+        assert_eq!((1, 15), (error.position.line, error.position.column));
+    }
+
+    #[test]
+    fn missing_identifier() {
+        let error = parse_complete("let x = 1\nlet = 5").unwrap_err();
+        assert_eq!(vec![Expected::Label("identifier")], error.expected);
+        assert_eq!(Some("=".to_string()), error.found);
+        assert_eq!((2, 5), (error.position.line, error.position.column));
+    }
+
+    #[test]
+    fn unassignable_operand() {
+        let error = parse_complete("++1").unwrap_err();
+        assert_eq!(
+            vec![Expected::Label("variable or property")],
+            error.expected
+        );
+        assert_eq!(Some("1".to_string()), error.found);
+
+        let error = parse_complete("let x = ").unwrap_err();
+        assert_eq!(vec![Expected::Label("expression")], error.expected);
+    }
+
+    #[test]
+    fn trailing_input() {
+        let error = parse_complete("let x = 1\n  )").unwrap_err();
+        assert_eq!(vec![Expected::Label("statement")], error.expected);
+        assert_eq!((2, 3), (error.position.line, error.position.column));
+    }
+
+    #[test]
+    fn bigint_out_of_range() {
+        let error = parse_complete("let x = 1\nlet y = 170141183460469231731687303715884105728n")
+            .unwrap_err();
+        assert_eq!(
+            vec![Expected::Label("BigInt literal of at most 127 bits")],
+            error.expected
+        );
+        assert_eq!((2, 9), (error.position.line, error.position.column));
+
+        let error = parse_complete("f(0x1_0000_0000_0000_0000_0000_0000_0000_0000n)").unwrap_err();
+        assert_eq!(
+            vec![Expected::Label("BigInt literal of at most 127 bits")],
+            error.expected
+        );
+    }
+
+    #[test]
+    fn display() {
+        let error = parse_complete("function f() {\n\tf(1 2)\n}").unwrap_err();
+        assert_eq!(
+            "expected `,` or `)`, found `2` at 2:6\n  |\n2 | \tf(1 2)\n  | \t    ^",
+            error.to_string()
+        );
+
+        assert_eq!("'`'", Expected::Char('`').to_string());
+
+        let error = parse_complete("throw\nx").unwrap_err();
+        assert_eq!(
+            "expected expression, found line break at 1:6\n  |\n1 | throw\n  |      ^",
+            error.to_string()
+        );
+
+        // Errors, which aren't located yet, are displayed nonetheless
+        let mut error = parse_complete("let = 1").unwrap_err();
+        error.position.line = 0;
+        error.position.column = 0;
+        assert!(error
+            .to_string()
+            .starts_with("expected identifier, found `=` at 0:0"));
+        assert_eq!("`)`", Expected::Char(')').to_string());
+    }
+
+    #[test]
+    fn synchronize_boundaries() {
+        assert_eq!("\nnext", synchronize("= 1\nnext"));
+        assert_eq!(" next", synchronize("= 1; next"));
+        assert_eq!("}", synchronize("= 1 }"));
+        assert_eq!("\nnext", synchronize("f({\n a: \"}\"\n})\nnext"));
+        assert_eq!("\nnext", synchronize("= 1 // ; }\nnext"));
+        assert_eq!("\nnext", synchronize("= /* ; } */ 1\nnext"));
+    }
+
+    #[test]
+    fn recover_all_errors() {
+        let source = "let = 1\nlet x = 2\nfunction f() {\n  x = )\n  return x\n}\nlet y = (3";
+        let (ast, errors) = parse_recovering(source);
+
+        let positions: Vec<_> = errors
+            .iter()
+            .map(|error| (error.position.line, error.position.column))
+            .collect();
+        assert_eq!(vec![(1, 5), (4, 7), (7, 11)], positions);
+
+        assert_eq!(3, ast.instructions.len());
+        assert!(matches!(ast.instructions[0].kind, StatementKind::Error(_)));
+        assert!(matches!(
+            ast.instructions[1].kind,
+            StatementKind::Declaration(_)
+        ));
+
+        let body = &ast.functions[0].body;
+        assert!(matches!(body.instructions[0].kind, StatementKind::Error(_)));
+        assert!(matches!(
+            body.instructions[1].kind,
+            StatementKind::Return(_)
+        ));
+    }
+
+    #[test]
+    fn recover_stray_brace() {
+        let (ast, errors) = parse_recovering("let x = 1\n}\nlet y = 2");
+        assert_eq!(1, errors.len());
+        assert_eq!(Some("}".to_string()), errors[0].found);
+        assert_eq!(3, ast.instructions.len());
+    }
+
+    #[test]
+    fn recover_in_switch() {
+        let source = "switch (x) {\n  case 1: x = )\n  default: x = 2\n}\ncase 3";
+        let (ast, errors) = parse_recovering(source);
+
+        let positions: Vec<_> = errors
+            .iter()
+            .map(|error| (error.position.line, error.position.column))
+            .collect();
+        assert_eq!(vec![(2, 15), (5, 1)], positions);
+
+        match &ast.instructions[0].kind {
+            StatementKind::Switch(switch) => assert_eq!(2, switch.cases.len()),
+            other => panic!("expected a switch, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn state_after_panic() {
+        use super::{expected, isolated, recovering, reset, FURTHEST, RECOVERING};
+        use std::cell::Cell;
+
+        reset();
+        expected("abc", Expected::Char('x'));
+        let result =
+            std::panic::catch_unwind(|| recovering(|| isolated::<()>(|| panic!("parser bug"))));
+        assert!(result.is_err());
+        assert!(!RECOVERING.with(Cell::get));
+        let noted = FURTHEST.with(|furthest| furthest.borrow().clone());
+        assert_eq!(
+            Some(vec![Expected::Char('x')]),
+            noted.map(|failure| failure.expected)
+        );
+        reset();
+    }
+
+    #[test]
+    fn recover_nothing() {
+        let (ast, errors) = parse_recovering("let x = 1\nx = x + 1");
+        assert!(errors.is_empty());
+        assert_eq!(2, ast.instructions.len());
+    }
+}
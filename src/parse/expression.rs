@@ -1,20 +1,37 @@
 use crate::parse::{
-    char_ws, concat, fold_concat, identifier::Identifier, ignore_ws, not_followed, obj::Object,
-    tag_ws,
+    char_ws, concat,
+    error::{self, CollectErrors, SyntaxError},
+    identifier::{is_id_continue, is_id_start, Identifier},
+    ignore_ws, keyword, not_followed,
+    obj::Object,
+    span::{LineIndex, Locate, Span},
+    spanned,
+    string_template::StringTemplate,
+    whitespace,
 };
 use nom::{
     branch::alt,
     bytes::complete::tag,
     character::complete::char,
-    combinator::map,
-    sequence::{delimited, pair, preceded, separated_pair},
+    combinator::{map, peek},
+    multi::separated_nonempty_list,
+    sequence::{delimited, preceded, separated_pair},
     IResult,
 };
 
 #[derive(Debug)]
-pub enum Expr {
+pub struct Expr {
+    pub kind: ExprKind,
+    pub span: Span,
+}
+
+#[derive(Debug)]
+pub enum ExprKind {
+    /// Comma operator, like `i++, j--`, resulting in the last value
+    Sequence(Vec<Expr>),
+    /// Assignment to a variable or a property
     Mutate {
-        variable: Identifier,
+        target: Box<Expr>,
         mutation: MutationKind,
         assign: Box<Expr>,
     },
@@ -25,13 +42,22 @@ pub enum Expr {
     },
     Or(Box<Expr>, Box<Expr>),
     And(Box<Expr>, Box<Expr>),
-    Xor(Box<Expr>, Box<Expr>),
+    BitOr(Box<Expr>, Box<Expr>),
+    BitXor(Box<Expr>, Box<Expr>),
+    BitAnd(Box<Expr>, Box<Expr>),
     Equal(Box<Expr>, Box<Expr>),
     NotEqual(Box<Expr>, Box<Expr>),
+    StrictEqual(Box<Expr>, Box<Expr>),
+    StrictNotEqual(Box<Expr>, Box<Expr>),
     SmallerEq(Box<Expr>, Box<Expr>),
     GreaterEq(Box<Expr>, Box<Expr>),
     Smaller(Box<Expr>, Box<Expr>),
     Greater(Box<Expr>, Box<Expr>),
+    InstanceOf(Box<Expr>, Box<Expr>),
+    In(Box<Expr>, Box<Expr>),
+    LeftShift(Box<Expr>, Box<Expr>),
+    RightShift(Box<Expr>, Box<Expr>),
+    UnsignedRightShift(Box<Expr>, Box<Expr>),
     Add(Box<Expr>, Box<Expr>),
     Sub(Box<Expr>, Box<Expr>),
     Div(Box<Expr>, Box<Expr>),
@@ -40,112 +66,180 @@ pub enum Expr {
     Exponent(Box<Expr>, Box<Expr>),
     Not(Box<Expr>),
     Neg(Box<Expr>),
-    Identifier {
-        path: Vec<Identifier>,
-        action: Option<Action>,
+    /// Unary `+x`, converting `x` to a number
+    Plus(Box<Expr>),
+    BitNot(Box<Expr>),
+    TypeOf(Box<Expr>),
+    /// `void x`, evaluating `x` and resulting in `undefined`
+    Void(Box<Expr>),
+    /// `delete a.b`, removing a property
+    Delete(Box<Expr>),
+    /// `++x`, evaluating to the value after the increment
+    PreIncrement(Box<Expr>),
+    /// `--x`, evaluating to the value after the decrement
+    PreDecrement(Box<Expr>),
+    /// `x++`, evaluating to the value before the increment
+    PostIncrement(Box<Expr>),
+    /// `x--`, evaluating to the value before the decrement
+    PostDecrement(Box<Expr>),
+    /// `a ?? b`, evaluating `b` only if `a` is `null` or `undefined`
+    Nullish(Box<Expr>, Box<Expr>),
+    /// Property access, like `a.b`, or `a?.b` if optional
+    Member {
+        object: Box<Expr>,
+        property: Identifier,
+        optional: bool,
+    },
+    /// Property access with a computed key, like `a[b]`, or `a?.[b]` if optional
+    ComputedMember {
+        object: Box<Expr>,
+        property: Box<Expr>,
+        optional: bool,
+    },
+    /// Function call, like `f(x)`, or `f?.(x)` if optional
+    Call {
+        callee: Box<Expr>,
+        arguments: Vec<Expr>,
+        optional: bool,
     },
+    /// Chain of property accesses and calls containing optional links, like `a?.b.c()`.
+    /// Once an optional link finds `null` or `undefined`, the whole chain is `undefined`.
+    OptionalChain(Box<Expr>),
+    /// Tagged template, calling the tag with the strings and interpolated values
+    Tag {
+        tag: Box<Expr>,
+        template: StringTemplate,
+    },
+    Identifier(Identifier),
     Value(Object),
-    // TODO bitshift
 }
 
-#[derive(Debug)]
-pub enum Action {
-    Increase,
-    Decrease,
-    Get { index: Box<Expr> },
-    Call { arguments: Vec<Expr> },
+/// Property access, call or tagged template following an expression
+enum Link {
+    Member(Identifier),
+    ComputedMember(Box<Expr>),
+    Call(Vec<Expr>),
+    Tag(StringTemplate),
 }
 
-impl Action {
-    fn parse(input: &str) -> IResult<&str, Action> {
-        ignore_ws(alt((
-            map(tag("++"), |_| Action::Increase),
-            map(tag("--"), |_| Action::Decrease),
+impl Link {
+    /// Link and whether it is optional, like `?.b`
+    fn parse(input: &str) -> IResult<&str, (Link, bool)> {
+        alt((
             map(
-                delimited(
-                    char('('),
-                    concat(char_ws(','), ignore_ws(Expr::parse)),
-                    char_ws(')'),
-                ),
-                |arguments| Action::Call { arguments },
+                alt((
+                    preceded(char('.'), Link::member),
+                    Link::computed_member,
+                    Link::call,
+                    map(
+                        preceded(peek(char('`')), StringTemplate::parse_tagged),
+                        Link::Tag,
+                    ),
+                )),
+                |link| (link, false),
             ),
             map(
-                delimited(char('['), ignore_ws(Expr::parse), char_ws(']')),
-                |expr| Action::Get {
-                    index: expr.boxed(),
-                },
+                preceded(
+                    tag("?."),
+                    ignore_ws(alt((Link::member, Link::computed_member, Link::call))),
+                ),
+                |link| (link, true),
             ),
-        )))(input)
+        ))(input)
     }
-}
-
-#[cfg(test)]
-mod action_test {
-    use super::Action;
-    #[test]
-    fn get() {
-        let input = "[314]";
-        let result = dbg!(Action::parse(input));
-
-        assert!(result.is_ok());
 
-        assert_eq!("", result.unwrap().0);
+    fn member(input: &str) -> IResult<&str, Link> {
+        map(Identifier::parse_name_ws, Link::Member)(input)
     }
 
-    #[test]
-    fn call_1() {
-        let input = "()";
-        let result = dbg!(Action::parse(input));
-
-        assert!(result.is_ok());
-
-        assert_eq!("", result.unwrap().0);
+    fn computed_member(input: &str) -> IResult<&str, Link> {
+        map(
+            delimited(char('['), ignore_ws(Expr::parse_sequence), char_ws(']')),
+            |property| Link::ComputedMember(property.boxed()),
+        )(input)
     }
 
-    #[test]
-    fn call_2() {
-        let input = "(1)";
-        let result = dbg!(Action::parse(input));
-
-        assert!(result.is_ok());
-
-        assert_eq!("", result.unwrap().0);
+    fn call(input: &str) -> IResult<&str, Link> {
+        map(
+            delimited(
+                char('('),
+                concat(char_ws(','), ignore_ws(Expr::parse)),
+                char_ws(')'),
+            ),
+            Link::Call,
+        )(input)
     }
 
-    #[test]
-    fn call_3() {
-        let input = "(1, 2)";
-        let result = dbg!(Action::parse(input));
-
-        assert!(result.is_ok());
-
-        assert_eq!("", result.unwrap().0);
+    /// Expression applying the link to `object`
+    fn apply(self, object: Box<Expr>, optional: bool) -> ExprKind {
+        match self {
+            Link::Member(property) => ExprKind::Member {
+                object,
+                property,
+                optional,
+            },
+            Link::ComputedMember(property) => ExprKind::ComputedMember {
+                object,
+                property,
+                optional,
+            },
+            Link::Call(arguments) => ExprKind::Call {
+                callee: object,
+                arguments,
+                optional,
+            },
+            Link::Tag(template) => ExprKind::Tag {
+                tag: object,
+                template,
+            },
+        }
     }
 }
 
 #[derive(Debug)]
 pub enum MutationKind {
-    Assign,         // =
-    AddAssign,      // +=
-    SubtractAssign, // -=
-    ModAssign,      // %=
-    MulAssign,      // *=
-    DivAssign,      // /=
+    Assign,                   // =
+    AddAssign,                // +=
+    SubtractAssign,           // -=
+    ModAssign,                // %=
+    MulAssign,                // *=
+    DivAssign,                // /=
+    ExponentAssign,           // **=
+    LeftShiftAssign,          // <<=
+    RightShiftAssign,         // >>=
+    UnsignedRightShiftAssign, // >>>=
+    BitAndAssign,             // &=
+    BitOrAssign,              // |=
+    BitXorAssign,             // ^=
+    AndAssign,                // &&=
+    OrAssign,                 // ||=
+    NullishAssign,            // ??=
 }
 
 impl MutationKind {
+    /// Longer tokens are tried first, so that `>>=` isn't taken for `>=`
     fn parse(input: &str) -> IResult<&str, MutationKind> {
         use crate::parse::whitespace;
         use MutationKind::*;
         preceded(
             whitespace,
             alt((
-                tag("="),
+                tag(">>>="),
+                tag("**="),
+                tag("<<="),
+                tag(">>="),
+                tag("&&="),
+                tag("||="),
+                tag("??="),
                 tag("+="),
                 tag("-="),
                 tag("%="),
                 tag("*="),
                 tag("/="),
+                tag("&="),
+                tag("|="),
+                tag("^="),
+                not_followed(tag("="), char('=')),
             )),
         )(input)
         .map(|(i, r)| {
@@ -158,6 +252,16 @@ impl MutationKind {
                     "%=" => ModAssign,
                     "*=" => MulAssign,
                     "/=" => DivAssign,
+                    "**=" => ExponentAssign,
+                    "<<=" => LeftShiftAssign,
+                    ">>=" => RightShiftAssign,
+                    ">>>=" => UnsignedRightShiftAssign,
+                    "&=" => BitAndAssign,
+                    "|=" => BitOrAssign,
+                    "^=" => BitXorAssign,
+                    "&&=" => AndAssign,
+                    "||=" => OrAssign,
+                    "??=" => NullishAssign,
                     _ => unreachable!(),
                 },
             )
@@ -165,42 +269,186 @@ impl MutationKind {
     }
 }
 
+type BinaryKind = fn(Box<Expr>, Box<Expr>) -> ExprKind;
+type UnaryKind = fn(Box<Expr>) -> ExprKind;
+
+/// Binary operator of the ECMAScript operator precedence table
+struct Operator {
+    token: &'static str,
+    /// Operators with higher precedence bind tighter
+    precedence: u8,
+    right_associative: bool,
+    kind: BinaryKind,
+}
+
+/// All binary operators, assignment and the conditional operator
+/// are handled by `Expr::parse` and `Expr::elvis`, `??` by `Expr::short_circuit`
+const OPERATORS: &[Operator] = &[
+    Operator::left("||", 3, ExprKind::Or),
+    Operator::left("&&", 4, ExprKind::And),
+    Operator::left("|", 5, ExprKind::BitOr),
+    Operator::left("^", 6, ExprKind::BitXor),
+    Operator::left("&", 7, ExprKind::BitAnd),
+    Operator::left("==", 8, ExprKind::Equal),
+    Operator::left("!=", 8, ExprKind::NotEqual),
+    Operator::left("===", 8, ExprKind::StrictEqual),
+    Operator::left("!==", 8, ExprKind::StrictNotEqual),
+    Operator::left("<", 9, ExprKind::Smaller),
+    Operator::left(">", 9, ExprKind::Greater),
+    Operator::left("<=", 9, ExprKind::SmallerEq),
+    Operator::left(">=", 9, ExprKind::GreaterEq),
+    Operator::left("instanceof", 9, ExprKind::InstanceOf),
+    Operator::left("in", 9, ExprKind::In),
+    Operator::left("<<", 10, ExprKind::LeftShift),
+    Operator::left(">>", 10, ExprKind::RightShift),
+    Operator::left(">>>", 10, ExprKind::UnsignedRightShift),
+    Operator::left("+", 11, ExprKind::Add),
+    Operator::left("-", 11, ExprKind::Sub),
+    Operator::left("*", 12, ExprKind::Mul),
+    Operator::left("/", 12, ExprKind::Div),
+    Operator::left("%", 12, ExprKind::Mod),
+    Operator::right("**", 13, ExprKind::Exponent),
+];
+
+/// Precedence of `|`, which binds the operands of `??`
+const BIT_OR: u8 = 5;
+
+impl Operator {
+    const fn left(token: &'static str, precedence: u8, kind: BinaryKind) -> Operator {
+        Operator {
+            token,
+            precedence,
+            right_associative: false,
+            kind,
+        }
+    }
+
+    const fn right(token: &'static str, precedence: u8, kind: BinaryKind) -> Operator {
+        Operator {
+            token,
+            precedence,
+            right_associative: true,
+            kind,
+        }
+    }
+
+    /// Recognize the longest operator at the start of `input`.
+    /// An operator followed by `=` is a compound assignment instead,
+    /// a doubled `+` or `-` an increment or decrement,
+    /// keyword operators need to be whole words.
+    fn parse(input: &str) -> IResult<&str, &'static Operator> {
+        let (input, _) = whitespace(input)?;
+        let operator = OPERATORS
+            .iter()
+            .filter(|operator| match input.strip_prefix(operator.token) {
+                Some(rest) => {
+                    !operator.token.starts_with(is_id_start) || !rest.starts_with(is_id_continue)
+                }
+                None => false,
+            })
+            .max_by_key(|operator| operator.token.len());
+
+        match operator {
+            Some(operator) => {
+                let rest = &input[operator.token.len()..];
+                let increment =
+                    matches!(operator.token, "+" | "-") && rest.starts_with(operator.token);
+                if rest.starts_with('=') || increment {
+                    return Err(nom::Err::Error((input, nom::error::ErrorKind::Tag)));
+                }
+                Ok((rest, operator))
+            }
+            None => Err(nom::Err::Error((input, nom::error::ErrorKind::Tag))),
+        }
+    }
+}
+
 impl Expr {
     fn boxed(self) -> Box<Self> {
         Box::new(self)
     }
 
+    /// Expression spanning from `left` to `right`
+    fn binary(left: Expr, right: Expr, kind: BinaryKind) -> Expr {
+        Expr {
+            span: left.span.to(right.span),
+            kind: kind(left.boxed(), right.boxed()),
+        }
+    }
+
+    /// Variables and properties can be assigned to
+    pub fn is_assignable(&self) -> bool {
+        matches!(
+            self.kind,
+            ExprKind::Identifier(_) | ExprKind::Member { .. } | ExprKind::ComputedMember { .. }
+        )
+    }
+
     pub fn parse(i: &str) -> IResult<&str, Expr> {
-        if let Ok((rest, (variable, mutation))) = pair(Identifier::parse_ws, MutationKind::parse)(i)
-        {
-            let (rest, assign) = map(Expr::parse, Box::new)(rest)?;
-            return Ok((
-                rest,
-                Expr::Mutate {
-                    variable,
-                    mutation,
-                    assign,
-                },
-            ));
+        let (rest, expr) = ignore_ws(Expr::elvis)(i)?;
+        if !expr.is_assignable() {
+            return Ok((rest, expr));
+        }
+
+        match MutationKind::parse(rest) {
+            Ok((rest, mutation)) => {
+                let (rest, assign) = map(Expr::parse, Box::new)(rest)?;
+                Ok((
+                    rest,
+                    Expr {
+                        span: expr.span.to(assign.span),
+                        kind: ExprKind::Mutate {
+                            target: expr.boxed(),
+                            mutation,
+                            assign,
+                        },
+                    },
+                ))
+            }
+            Err(_) => Ok((rest, expr)),
         }
+    }
 
-        ignore_ws(Expr::elvis)(i)
+    /// Expressions separated by the comma operator, like `a++, b++` or the mutation of
+    /// ```js
+    /// for (let i = 0, j = n; i < j; i++, j--) {}
+    /// ```
+    /// Wherever a list of expressions is separated by commas,
+    /// like arguments, the single expressions are parsed by `Expr::parse` instead.
+    pub fn parse_sequence(input: &str) -> IResult<&str, Expr> {
+        let (input, mut exprs) =
+            separated_nonempty_list(error::quiet(char_ws(',')), Expr::parse)(input)?;
+        if exprs.len() == 1 {
+            return Ok((input, exprs.pop().unwrap()));
+        }
+
+        let span = exprs[0].span.to(exprs[exprs.len() - 1].span);
+        Ok((
+            input,
+            Expr {
+                span,
+                kind: ExprKind::Sequence(exprs),
+            },
+        ))
     }
 
     pub fn elvis(input: &str) -> IResult<&str, Expr> {
-        let (input, expr) = Expr::or(input)?;
+        let (input, expr) = Expr::short_circuit(input)?;
 
         if let Ok((input, (case_true, case_false))) = preceded(
-            char_ws('?'),
+            error::quiet(char_ws('?')),
             separated_pair(Expr::parse, char_ws(':'), Expr::parse),
         )(input)
         {
             return Ok((
                 input,
-                Expr::Elvis {
-                    condition: expr.boxed(),
-                    case_true: case_true.boxed(),
-                    case_false: case_false.boxed(),
+                Expr {
+                    span: expr.span.to(case_false.span),
+                    kind: ExprKind::Elvis {
+                        condition: expr.boxed(),
+                        case_true: case_true.boxed(),
+                        case_false: case_false.boxed(),
+                    },
                 },
             ));
         }
@@ -208,136 +456,364 @@ impl Expr {
         Ok((input, expr))
     }
 
-    fn or(input: &str) -> IResult<&str, Expr> {
-        fold_concat(tag_ws("||"), Expr::and, |acc, e| {
-            Expr::Or(acc.boxed(), e.boxed())
-        })(input)
-    }
+    /// Either `||` and `&&`, or a chain of `??`.
+    /// Mixing them needs parentheses, like `(a || b) ?? c`.
+    fn short_circuit(input: &str) -> IResult<&str, Expr> {
+        let (mut input, mut left) = Expr::operators(input, BIT_OR)?;
+
+        if Expr::nullish(input).is_ok() {
+            while let Ok((rest, _)) = Expr::nullish(input) {
+                let (rest, right) = Expr::operators(rest, BIT_OR)?;
+                left = Expr::binary(left, right, ExprKind::Nullish);
+                input = rest;
+            }
+        } else {
+            let (rest, expr) = Expr::climb(input, left, false, 0)?;
+            input = rest;
+            left = expr;
+        }
 
-    fn and(input: &str) -> IResult<&str, Expr> {
-        fold_concat(tag_ws("&&"), Expr::xor, |acc, e| {
-            Expr::And(acc.boxed(), e.boxed())
-        })(input)
-    }
+        // Whatever follows now, is the other kind of operator
+        if Expr::nullish(input).is_ok() || Operator::parse(input).is_ok() {
+            let (operator, _) = whitespace(input)?;
+            error::expected(
+                operator,
+                error::Expected::Label("parentheses to mix `??` with `||` or `&&`"),
+            );
+            return Err(nom::Err::Error((operator, nom::error::ErrorKind::Verify)));
+        }
 
-    fn xor(input: &str) -> IResult<&str, Expr> {
-        fold_concat(tag_ws("^"), Expr::equal, |acc, e| {
-            Expr::Xor(acc.boxed(), e.boxed())
-        })(input)
+        Ok((input, left))
     }
 
-    fn equal(input: &str) -> IResult<&str, Expr> {
-        fold_concat(tag_ws("=="), Expr::not_equal, |acc, e| {
-            Expr::Equal(acc.boxed(), e.boxed())
-        })(input)
+    /// `??`, but not `??=`
+    fn nullish(input: &str) -> IResult<&str, &str> {
+        let (input, _) = whitespace(input)?;
+        not_followed(tag("??"), char('='))(input)
     }
 
-    fn not_equal(input: &str) -> IResult<&str, Expr> {
-        fold_concat(tag_ws("!="), Expr::greater_eq, |acc, e| {
-            Expr::NotEqual(acc.boxed(), e.boxed())
-        })(input)
+    /// Binary operators by precedence climbing.
+    /// Only operators binding at least as tight as `precedence` are consumed,
+    /// the operands of right associative operators may contain the operator again.
+    fn operators(input: &str, precedence: u8) -> IResult<&str, Expr> {
+        let prefixed = Expr::prefix(whitespace(input)?.0).is_ok();
+        let (input, left) = Expr::unary(input)?;
+        Expr::climb(input, left, prefixed, precedence)
     }
 
-    fn greater_eq(input: &str) -> IResult<&str, Expr> {
-        fold_concat(tag_ws(">="), Expr::smaller_eq, |acc, e| {
-            Expr::GreaterEq(acc.boxed(), e.boxed())
-        })(input)
-    }
+    /// Continue the operators after `left`, which is a unary expression if `prefixed`
+    fn climb(
+        mut input: &str,
+        mut left: Expr,
+        prefixed: bool,
+        precedence: u8,
+    ) -> IResult<&str, Expr> {
+        while let Ok((rest, operator)) = Operator::parse(input) {
+            if operator.precedence < precedence {
+                break;
+            }
+            // `-x ** y` is ambiguous and needs parentheses.
+            // `**` binds tightest, so it always belongs to the operand just parsed.
+            if prefixed && operator.token == "**" {
+                let (operator, _) = whitespace(input)?;
+                error::expected(
+                    operator,
+                    error::Expected::Label("parentheses around the unary operand of `**`"),
+                );
+                return Err(nom::Err::Error((operator, nom::error::ErrorKind::Verify)));
+            }
+
+            let precedence = if operator.right_associative {
+                operator.precedence
+            } else {
+                operator.precedence + 1
+            };
+            let (rest, right) = Expr::operators(rest, precedence)?;
+            left = Expr::binary(left, right, operator.kind);
+            input = rest;
+        }
 
-    fn smaller_eq(input: &str) -> IResult<&str, Expr> {
-        fold_concat(tag_ws("<="), Expr::greater, |acc, e| {
-            Expr::SmallerEq(acc.boxed(), e.boxed())
-        })(input)
+        Ok((input, left))
     }
 
-    fn greater(input: &str) -> IResult<&str, Expr> {
-        fold_concat(tag_ws(">"), Expr::smaller, |acc, e| {
-            Expr::Greater(acc.boxed(), e.boxed())
-        })(input)
+    fn prefix(input: &str) -> IResult<&str, UnaryKind> {
+        alt((
+            map(not_followed(char('-'), char('-')), |_| {
+                ExprKind::Neg as UnaryKind
+            }),
+            map(not_followed(char('+'), char('+')), |_| {
+                ExprKind::Plus as UnaryKind
+            }),
+            map(char('!'), |_| ExprKind::Not as UnaryKind),
+            map(char('~'), |_| ExprKind::BitNot as UnaryKind),
+            map(error::quiet(keyword("typeof")), |_| {
+                ExprKind::TypeOf as UnaryKind
+            }),
+            map(error::quiet(keyword("void")), |_| {
+                ExprKind::Void as UnaryKind
+            }),
+            map(error::quiet(keyword("delete")), |_| {
+                ExprKind::Delete as UnaryKind
+            }),
+        ))(input)
     }
 
-    fn smaller(input: &str) -> IResult<&str, Expr> {
-        fold_concat(tag_ws("<"), Expr::add, |acc, e| {
-            Expr::Smaller(acc.boxed(), e.boxed())
-        })(input)
-    }
+    /// Variable or property, which can be assigned to
+    pub fn parse_target(input: &str) -> IResult<&str, Expr> {
+        let (input, _) = whitespace(input)?;
+        let (rest, target) = Expr::postfix(input)?;
+        if !target.is_assignable() {
+            error::expected(input, error::Expected::Label("variable or property"));
+            return Err(nom::Err::Error((input, nom::error::ErrorKind::Verify)));
+        }
 
-    fn add(input: &str) -> IResult<&str, Expr> {
-        fold_concat(tag_ws("+"), Expr::sub, |acc, e| {
-            Expr::Add(acc.boxed(), e.boxed())
-        })(input)
+        Ok((rest, target))
     }
 
-    fn sub(input: &str) -> IResult<&str, Expr> {
-        fold_concat(tag_ws("-"), Expr::div, |acc, e| {
-            Expr::Sub(acc.boxed(), e.boxed())
-        })(input)
+    /// Prefix `++` and `--`, which only apply to variables and properties
+    fn update(input: &str) -> IResult<&str, Expr> {
+        let (rest, (kind, span)) = spanned(alt((
+            map(tag("++"), |_| ExprKind::PreIncrement as UnaryKind),
+            map(tag("--"), |_| ExprKind::PreDecrement as UnaryKind),
+        )))(input)?;
+        let (rest, operand) = Expr::parse_target(rest)?;
+
+        Ok((
+            rest,
+            Expr {
+                span: span.to(operand.span),
+                kind: kind(operand.boxed()),
+            },
+        ))
     }
 
-    fn div(input: &str) -> IResult<&str, Expr> {
-        fold_concat(tag_ws("/"), Expr::mul, |acc, e| {
-            Expr::Div(acc.boxed(), e.boxed())
-        })(input)
-    }
+    fn unary(input: &str) -> IResult<&str, Expr> {
+        if let Ok(update) = Expr::update(input) {
+            return Ok(update);
+        }
 
-    fn mul(input: &str) -> IResult<&str, Expr> {
-        fold_concat(
-            not_followed(char_ws('*'), char('*')),
-            Expr::modulo,
-            |acc, e| Expr::Mul(acc.boxed(), e.boxed()),
-        )(input)
+        match spanned(Expr::prefix)(input) {
+            Ok((rest, (kind, span))) => {
+                let (rest, operand) = Expr::unary(rest)?;
+                Ok((
+                    rest,
+                    Expr {
+                        span: span.to(operand.span),
+                        kind: kind(operand.boxed()),
+                    },
+                ))
+            }
+            Err(_) => Expr::postfix(input),
+        }
     }
 
-    fn modulo(input: &str) -> IResult<&str, Expr> {
-        fold_concat(tag_ws("%"), Expr::preceding_sign, |acc, e| {
-            Expr::Mod(acc.boxed(), e.boxed())
-        })(input)
-    }
-    fn preceding_sign(input: &str) -> IResult<&str, Expr> {
-        if let Ok((input, _)) = char_ws('-')(input) {
-            let (input, e) = Expr::exponent(input)?;
-            return Ok((input, Expr::Neg(Box::new(e))));
+    /// Value followed by any number of property accesses, calls and tagged templates,
+    /// optionally ending in `++` or `--`
+    fn postfix(input: &str) -> IResult<&str, Expr> {
+        let (mut input, mut expr) = Expr::value(input)?;
+        let mut optional_chain = false;
+        while let Ok((rest, ((link, optional), span))) = spanned(Link::parse)(input) {
+            expr = Expr {
+                span: expr.span.to(span),
+                kind: link.apply(expr.boxed(), optional),
+            };
+            optional_chain |= optional;
+            input = rest;
         }
 
-        if let Ok((input, _)) = char_ws('!')(input) {
-            let (input, e) = Expr::exponent(input)?;
-            return Ok((input, Expr::Not(Box::new(e))));
+        if optional_chain {
+            return Ok((
+                input,
+                Expr {
+                    span: expr.span,
+                    kind: ExprKind::OptionalChain(expr.boxed()),
+                },
+            ));
         }
 
-        Expr::exponent(input)
-    }
+        // Postfix `++` and `--` need to be on the same line as their operand
+        let (rest, trivia) = whitespace(input)?;
+        if !expr.is_assignable() || trivia.contains('\n') {
+            return Ok((input, expr));
+        }
+        let postfix = spanned(alt((
+            map(tag("++"), |_| ExprKind::PostIncrement as UnaryKind),
+            map(tag("--"), |_| ExprKind::PostDecrement as UnaryKind),
+        )))(rest);
 
-    fn exponent(input: &str) -> IResult<&str, Expr> {
-        fold_concat(tag_ws("**"), Expr::value, |acc, e| {
-            Expr::Exponent(acc.boxed(), e.boxed())
-        })(input)
+        Ok(match postfix {
+            Ok((rest, (kind, span))) => (
+                rest,
+                Expr {
+                    span: expr.span.to(span),
+                    kind: kind(expr.boxed()),
+                },
+            ),
+            Err(_) => (input, expr),
+        })
     }
 
     fn value(input: &str) -> IResult<&str, Expr> {
-        ignore_ws(alt((
-            Expr::ident,
-            delimited(char('('), Expr::parse, char_ws(')')),
-            map(Object::parse, Object::as_expr),
-        )))(input)
+        ignore_ws(error::label(
+            "expression",
+            alt((
+                map(Object::parse_literal, Object::as_expr),
+                map(spanned(Identifier::parse), |(identifier, span)| Expr {
+                    span,
+                    kind: ExprKind::Identifier(identifier),
+                }),
+                map(
+                    spanned(delimited(char('('), Expr::parse_sequence, char_ws(')'))),
+                    |(expr, span)| Expr { span, ..expr },
+                ),
+                map(Object::parse, Object::as_expr),
+            )),
+        ))(input)
     }
+}
 
-    fn ident(input: &str) -> IResult<&str, Expr> {
-        let (rest, list) = concat(char_ws('.'), Identifier::parse_ws)(input)?;
-
-        if list.len() == 0 {
-            return Err(nom::Err::Error((
-                rest,
-                nom::error::ErrorKind::SeparatedList,
-            )));
+impl Locate for Expr {
+    fn locate(&mut self, lines: &LineIndex) {
+        use ExprKind::*;
+        self.span.locate(lines);
+        match &mut self.kind {
+            Sequence(exprs) => exprs.locate(lines),
+            Mutate { target, assign, .. } => {
+                target.locate(lines);
+                assign.locate(lines);
+            }
+            Elvis {
+                condition,
+                case_true,
+                case_false,
+            } => {
+                condition.locate(lines);
+                case_true.locate(lines);
+                case_false.locate(lines);
+            }
+            Or(a, b)
+            | And(a, b)
+            | BitOr(a, b)
+            | BitXor(a, b)
+            | BitAnd(a, b)
+            | Equal(a, b)
+            | NotEqual(a, b)
+            | StrictEqual(a, b)
+            | StrictNotEqual(a, b)
+            | SmallerEq(a, b)
+            | GreaterEq(a, b)
+            | Smaller(a, b)
+            | Greater(a, b)
+            | InstanceOf(a, b)
+            | In(a, b)
+            | LeftShift(a, b)
+            | RightShift(a, b)
+            | UnsignedRightShift(a, b)
+            | Add(a, b)
+            | Sub(a, b)
+            | Div(a, b)
+            | Mul(a, b)
+            | Mod(a, b)
+            | Exponent(a, b)
+            | Nullish(a, b) => {
+                a.locate(lines);
+                b.locate(lines);
+            }
+            Not(e) | Neg(e) | Plus(e) | BitNot(e) | TypeOf(e) | Void(e) | Delete(e)
+            | PreIncrement(e) | PreDecrement(e) | PostIncrement(e) | PostDecrement(e)
+            | OptionalChain(e) => e.locate(lines),
+            Member { object, .. } => object.locate(lines),
+            ComputedMember {
+                object, property, ..
+            } => {
+                object.locate(lines);
+                property.locate(lines);
+            }
+            Call {
+                callee, arguments, ..
+            } => {
+                callee.locate(lines);
+                arguments.locate(lines);
+            }
+            Tag { tag, template } => {
+                tag.locate(lines);
+                template.locate(lines);
+            }
+            Identifier(_) => {}
+            Value(value) => value.locate(lines),
         }
+    }
+}
 
-        let (rest, action) = if let Ok((rest, action)) = Action::parse(rest) {
-            (rest, Some(action))
-        } else {
-            (rest, None)
-        };
-
-        Ok((rest, Expr::Identifier { path: list, action }))
+impl CollectErrors for Expr {
+    fn collect_errors(&self, errors: &mut Vec<SyntaxError>) {
+        use ExprKind::*;
+        match &self.kind {
+            Sequence(exprs) => exprs.collect_errors(errors),
+            Mutate { target, assign, .. } => {
+                target.collect_errors(errors);
+                assign.collect_errors(errors);
+            }
+            Elvis {
+                condition,
+                case_true,
+                case_false,
+            } => {
+                condition.collect_errors(errors);
+                case_true.collect_errors(errors);
+                case_false.collect_errors(errors);
+            }
+            Or(a, b)
+            | And(a, b)
+            | BitOr(a, b)
+            | BitXor(a, b)
+            | BitAnd(a, b)
+            | Equal(a, b)
+            | NotEqual(a, b)
+            | StrictEqual(a, b)
+            | StrictNotEqual(a, b)
+            | SmallerEq(a, b)
+            | GreaterEq(a, b)
+            | Smaller(a, b)
+            | Greater(a, b)
+            | InstanceOf(a, b)
+            | In(a, b)
+            | LeftShift(a, b)
+            | RightShift(a, b)
+            | UnsignedRightShift(a, b)
+            | Add(a, b)
+            | Sub(a, b)
+            | Div(a, b)
+            | Mul(a, b)
+            | Mod(a, b)
+            | Exponent(a, b)
+            | Nullish(a, b) => {
+                a.collect_errors(errors);
+                b.collect_errors(errors);
+            }
+            Not(e) | Neg(e) | Plus(e) | BitNot(e) | TypeOf(e) | Void(e) | Delete(e)
+            | PreIncrement(e) | PreDecrement(e) | PostIncrement(e) | PostDecrement(e)
+            | OptionalChain(e) => e.collect_errors(errors),
+            Member { object, .. } => object.collect_errors(errors),
+            ComputedMember {
+                object, property, ..
+            } => {
+                object.collect_errors(errors);
+                property.collect_errors(errors);
+            }
+            Call {
+                callee, arguments, ..
+            } => {
+                callee.collect_errors(errors);
+                arguments.collect_errors(errors);
+            }
+            Tag { tag, template } => {
+                tag.collect_errors(errors);
+                template.collect_errors(errors);
+            }
+            Identifier(_) => {}
+            Value(value) => value.collect_errors(errors),
+        }
     }
 }
 
@@ -365,6 +841,21 @@ mod test {
         assert_eq!("", result.unwrap().0);
     }
 
+    #[test]
+    fn comparison_is_no_mutation() {
+        let result = dbg!(Expr::parse("a == b"));
+        assert!(matches!(
+            result,
+            Ok((
+                "",
+                Expr {
+                    kind: ExprKind::Equal(_, _),
+                    ..
+                }
+            ))
+        ));
+    }
+
     #[test]
     fn mutation_3() {
         let input = "one += two /= 12";
@@ -378,7 +869,7 @@ mod test {
     #[test]
     fn ident_1() {
         let input = " a . b . c";
-        let result = Expr::ident(input);
+        let result = Expr::postfix(input);
 
         assert!(result.is_ok());
 
@@ -388,7 +879,7 @@ mod test {
     #[test]
     fn ident_2() {
         let input = "a()";
-        let result = Expr::ident(input);
+        let result = Expr::postfix(input);
 
         assert!(result.is_ok());
 
@@ -398,13 +889,93 @@ mod test {
     #[test]
     fn ident_3() {
         let input = "a.b.c[7]";
-        let result = dbg!(Expr::ident(input));
+        let result = dbg!(Expr::postfix(input));
 
         assert!(result.is_ok());
 
         assert_eq!("", result.unwrap().0);
     }
 
+    #[test]
+    fn chains() {
+        assert_eq!("a.b().c", tree("a.b().c"));
+        assert_eq!("arr[0].x", tree("arr[0].x"));
+        assert_eq!("f()()", tree("f()()"));
+        assert_eq!("obj[value].m(1)", tree("obj[\"k\"].m(1)"));
+        assert_eq!("(+ a b).c", tree("(a + b).c"));
+        assert_eq!("f(1, (+ 2 3))`2`", tree("f(1, 2 + 3)`a${b}c`"));
+        assert_eq!("(- a.b[1])", tree("-a.b[1]"));
+    }
+
+    #[test]
+    fn prefix() {
+        assert_eq!("(++x a.b)", tree("++a.b"));
+        assert_eq!("(- (--x a))", tree("-\n--a"));
+        assert_eq!("(+ (+ a) (++x b))", tree("+a + ++b"));
+        assert_eq!("(- (- a) (- b))", tree("-a - -b"));
+        assert_eq!("(** (++x a) 2)", tree("++a ** 2"));
+        assert_eq!("(! (void (delete a[0])))", tree("!void delete a[0]"));
+        assert_eq!("(typeof voids)", tree("typeof voids"));
+        assert!(Expr::parse("++f()").is_err());
+        assert!(Expr::parse("++-a").is_err());
+        assert_eq!("\n++b", Expr::parse("a\n++b").unwrap().0);
+    }
+
+    #[test]
+    fn postfix() {
+        assert_eq!("(++ a.b)", tree("a.b++"));
+        assert_eq!("(-- a[0])", tree("a[0]--"));
+        assert_eq!("(+ (++ a) b)", tree("a++ + b"));
+        assert_eq!("\n++b", Expr::postfix("a\n++b").unwrap().0);
+        assert_eq!("++", Expr::parse("f()++").unwrap().0);
+    }
+
+    #[test]
+    fn optional_chains() {
+        assert_eq!("(?. a?.b)", tree("a?.b"));
+        assert_eq!("(?. a?.b.c?.[0]?.(1).d)", tree("a?.b.c?.[0]?.(1).d"));
+        assert_eq!("(?. a?.b).c", tree("(a?.b).c"));
+        assert_eq!("(? a 0.5 1)", tree("a?.5:1"));
+        assert_eq!(" = 1", Expr::parse("a?.b = 1").unwrap().0);
+    }
+
+    #[test]
+    fn nullish() {
+        assert_eq!("(?? (?? a b) c)", tree("a ?? b ?? c"));
+        assert_eq!("(? (?? a b) c d)", tree("a ?? b ? c : d"));
+        assert_eq!("(= a.b (?? c 1))", tree("a.b ??= c ?? 1"));
+        assert_eq!("(?? (|| a b) c)", tree("(a || b) ?? c"));
+        assert_eq!("(|| a (?? b c))", tree("a || (b ?? c)"));
+        assert_eq!("(?? a (| b c))", tree("a ?? b | c"));
+        assert!(Expr::parse("null ?? 1 || 2").is_err());
+        assert!(Expr::parse("a || b ?? c").is_err());
+        assert!(Expr::parse("a ?? b && c").is_err());
+        assert!(Expr::parse("a && b ?? c").is_err());
+    }
+
+    #[test]
+    fn keyword_members() {
+        assert_eq!("o.default", tree("o.default"));
+        assert_eq!("p.catch(f)", tree("p.catch(f)"));
+        assert_eq!("(?. o?.new)", tree("o?.new"));
+    }
+
+    #[test]
+    fn member_assignment() {
+        assert_eq!("(= a.b (= c[0] 1))", tree("a.b = c[0] = 1"));
+        assert_eq!("(= f().x 1)", tree("f().x = 1"));
+        assert_eq!(" = 1", Expr::parse("f() = 1").unwrap().0);
+        assert_eq!(" = 1", Expr::parse("a + b = 1").unwrap().0);
+    }
+
+    #[test]
+    fn arrow_functions() {
+        assert_eq!("f(value, 2)", tree("f(() => 1, 2)"));
+        assert_eq!("f(value, value)", tree("f(() => { return 1 }, () => 2)"));
+        assert_eq!("", Expr::parse("[(() => 1), 2]").unwrap().0);
+        assert_eq!("", Expr::parse("[() => 1, 2]").unwrap().0);
+    }
+
     #[test]
     fn expression_1() {
         assert!(Expr::parse("1").is_ok());
@@ -429,12 +1000,12 @@ mod test {
 
     #[test]
     fn sign_1() {
-        assert!(Expr::preceding_sign("-1").is_ok());
+        assert!(Expr::unary("-1").is_ok());
     }
 
     #[test]
     fn sign_2() {
-        assert!(Expr::preceding_sign("!1").is_ok());
+        assert!(Expr::unary("!1").is_ok());
     }
 
     #[test]
@@ -450,7 +1021,216 @@ mod test {
 
     #[test]
     fn exponent() {
-        assert!(Expr::exponent("1**1**1").is_ok());
+        assert_eq!("(** 1 (** 2 3))", tree("1**2**3"));
+        assert_eq!("(** (- 2) 2)", tree("(-2) ** 2"));
+        assert_eq!("(- (** 2 2))", tree("-(2 ** 2)"));
+        assert_eq!("(** 2 (- 1))", tree("2 ** -1"));
+        assert!(Expr::parse("-2 ** 2").is_err());
+        assert!(Expr::parse("2 * -3 ** 2").is_err());
+        assert!(Expr::parse("a + typeof b ** 2").is_err());
+        assert!(Expr::parse("2 ** -3 ** 2").is_err());
+        assert_eq!("(* 2 (** (- 3) 2))", tree("2 * (-3) ** 2"));
+        assert_eq!("(+ a (typeof (** b 2)))", tree("a + typeof (b ** 2)"));
+    }
+
+    /// Render the shape of an expression as s-expression
+    fn tree(input: &str) -> String {
+        fn render(expr: &Expr) -> String {
+            use ExprKind::*;
+            let (operator, operands): (&str, Vec<&Expr>) = match &expr.kind {
+                Sequence(exprs) => (",", exprs.iter().collect()),
+                Mutate { target, assign, .. } => ("=", vec![target, assign]),
+                Elvis {
+                    condition,
+                    case_true,
+                    case_false,
+                } => ("?", vec![condition, case_true, case_false]),
+                Or(a, b) => ("||", vec![a, b]),
+                Nullish(a, b) => ("??", vec![a, b]),
+                And(a, b) => ("&&", vec![a, b]),
+                BitOr(a, b) => ("|", vec![a, b]),
+                BitXor(a, b) => ("^", vec![a, b]),
+                BitAnd(a, b) => ("&", vec![a, b]),
+                Equal(a, b) => ("==", vec![a, b]),
+                NotEqual(a, b) => ("!=", vec![a, b]),
+                StrictEqual(a, b) => ("===", vec![a, b]),
+                StrictNotEqual(a, b) => ("!==", vec![a, b]),
+                SmallerEq(a, b) => ("<=", vec![a, b]),
+                GreaterEq(a, b) => (">=", vec![a, b]),
+                Smaller(a, b) => ("<", vec![a, b]),
+                Greater(a, b) => (">", vec![a, b]),
+                InstanceOf(a, b) => ("instanceof", vec![a, b]),
+                In(a, b) => ("in", vec![a, b]),
+                LeftShift(a, b) => ("<<", vec![a, b]),
+                RightShift(a, b) => (">>", vec![a, b]),
+                UnsignedRightShift(a, b) => (">>>", vec![a, b]),
+                Add(a, b) => ("+", vec![a, b]),
+                Sub(a, b) => ("-", vec![a, b]),
+                Div(a, b) => ("/", vec![a, b]),
+                Mul(a, b) => ("*", vec![a, b]),
+                Mod(a, b) => ("%", vec![a, b]),
+                Exponent(a, b) => ("**", vec![a, b]),
+                Not(e) => ("!", vec![e]),
+                Neg(e) => ("-", vec![e]),
+                BitNot(e) => ("~", vec![e]),
+                TypeOf(e) => ("typeof", vec![e]),
+                Plus(e) => ("+", vec![e]),
+                Void(e) => ("void", vec![e]),
+                Delete(e) => ("delete", vec![e]),
+                PreIncrement(e) => ("++x", vec![e]),
+                PreDecrement(e) => ("--x", vec![e]),
+                PostIncrement(e) => ("++", vec![e]),
+                PostDecrement(e) => ("--", vec![e]),
+                Member {
+                    object,
+                    property,
+                    optional,
+                } => {
+                    let link = if *optional { "?." } else { "." };
+                    return format!("{}{}{}", render(object), link, property.0);
+                }
+                ComputedMember {
+                    object,
+                    property,
+                    optional,
+                } => {
+                    let link = if *optional { "?." } else { "" };
+                    return format!("{}{}[{}]", render(object), link, render(property));
+                }
+                Call {
+                    callee,
+                    arguments,
+                    optional,
+                } => {
+                    let link = if *optional { "?." } else { "" };
+                    let arguments: Vec<_> = arguments.iter().map(render).collect();
+                    return format!("{}{}({})", render(callee), link, arguments.join(", "));
+                }
+                OptionalChain(e) => ("?.", vec![e]),
+                Tag { tag, template } => {
+                    return format!("{}`{}`", render(tag), template.end.len() + 1)
+                }
+                Identifier(identifier) => return identifier.0.clone(),
+                Value(Object {
+                    kind: crate::parse::obj::ObjectKind::Number(n),
+                    ..
+                }) => return n.to_string(),
+                Value(Object {
+                    kind: crate::parse::obj::ObjectKind::Null,
+                    ..
+                }) => return "null".to_string(),
+                Value(_) => return "value".to_string(),
+            };
+            let operands: Vec<_> = operands.into_iter().map(render).collect();
+            format!("({} {})", operator, operands.join(" "))
+        }
+
+        let (rest, expr) = Expr::parse_sequence(input).unwrap();
+        assert_eq!("", rest);
+        render(&expr)
+    }
+
+    #[test]
+    fn left_associative() {
+        assert_eq!("(+ (- 1 2) 3)", tree("1-2+3"));
+        assert_eq!("(* (/ 8 2) 2)", tree("8/2*2"));
+        assert_eq!("(% (* a b) c)", tree("a*b%c"));
+        assert_eq!("(|| (|| a b) c)", tree("a || b || c"));
+    }
+
+    #[test]
+    fn precedence() {
+        assert_eq!("(+ 1 (* 2 3))", tree("1 + 2 * 3"));
+        assert_eq!("(* (+ 1 2) 3)", tree("(1 + 2) * 3"));
+        assert_eq!("(- (* 2 (** 3 2)) 1)", tree("2 * 3 ** 2 - 1"));
+        assert_eq!("(== (< a b) (> c d))", tree("a < b == c > d"));
+        assert_eq!("(^ (== a b) c)", tree("a == b ^ c"));
+        assert_eq!("(|| a (&& b (^ c d)))", tree("a || b && c ^ d"));
+        assert_eq!("(== (! a) (- b))", tree("!a == -b"));
+        assert_eq!("(- (- 1))", tree("- -1"));
+    }
+
+    #[test]
+    fn bitwise() {
+        assert_eq!("(|| (| a (^ b (& c d))) e)", tree("a | b ^ c & d || e"));
+        assert_eq!("(&& (& flags (== 4 4)) x)", tree("flags & 4 == 4 && x"));
+        assert_eq!("(== (& flags 4) 4)", tree("(flags & 4) == 4"));
+        assert_eq!("(< (<< 1 (+ n 1)) (>>> x 2))", tree("1 << n + 1 < x >>> 2"));
+        assert_eq!("(>> (>> a b) c)", tree("a >> b >> c"));
+        assert_eq!("(& (~ a) (- b))", tree("~a & -b"));
+        assert_eq!(" >>= 1", Expr::parse("f() >>= 1").unwrap().0);
+    }
+
+    #[test]
+    fn relational() {
+        assert_eq!("(=== a b)", tree("a === b"));
+        assert_eq!("(!== (== a b) c)", tree("a == b !== c"));
+        assert_eq!(
+            "(=== (instanceof a F) (in k o))",
+            tree("a instanceof F === k in o")
+        );
+        assert_eq!("(in (+ i 1) list)", tree("i + 1 in list"));
+        assert_eq!(" index", Expr::parse("a index").unwrap().0);
+        assert_eq!(" instanceofF", Expr::parse("a instanceofF").unwrap().0);
+    }
+
+    #[test]
+    fn literals() {
+        assert_eq!("(== a null)", tree("a == null"));
+        assert_eq!("(typeof (+ a 1))", tree("typeof (a + 1)"));
+        assert_eq!("(=== (typeof a) value)", tree("typeof a === \"undefined\""));
+        assert_eq!("(! NaN)", tree("!NaN"));
+        assert_eq!("nullable", tree("nullable"));
+        assert_eq!("typeofx", tree("typeofx"));
+    }
+
+    #[test]
+    fn conditional_and_assignment() {
+        assert_eq!(
+            "(? (== a 1) (+ b 1) (- c 1))",
+            tree("a == 1 ? b + 1 : c - 1")
+        );
+        assert_eq!("(? a b (? c d e))", tree("a ? b : c ? d : e"));
+        assert_eq!("(= a (= b (+ c 1)))", tree("a = b = c + 1"));
+        assert_eq!("(= a (? b c d))", tree("a = b ? c : d"));
+    }
+
+    #[test]
+    fn sequence() {
+        assert_eq!("(, (= a 1) (+ b 2) c)", tree("a = 1, b + 2, c"));
+        assert_eq!("(? a b c)", tree("a ? b : c"));
+        assert_eq!(", 2", Expr::parse("1, 2").unwrap().0);
+        assert_eq!("(= x (, a b))", tree("x = (a, b)"));
+        assert_eq!("a[(, 1 2)]", tree("a[1, 2]"));
+        assert_eq!("f(a, b)", tree("f(a, b)"));
+    }
+
+    #[test]
+    fn compound_assignment() {
+        let mutation = |input| match Expr::parse(input) {
+            Ok(("", expr)) => match expr.kind {
+                ExprKind::Mutate { mutation, .. } => format!("{:?}", mutation),
+                other => panic!("expected an assignment, got {:?}", other),
+            },
+            other => panic!("expected an assignment, got {:?}", other),
+        };
+        assert_eq!("AddAssign", mutation("a += 1"));
+        assert_eq!("ExponentAssign", mutation("a **= 2"));
+        assert_eq!("LeftShiftAssign", mutation("a <<= 1"));
+        assert_eq!("RightShiftAssign", mutation("a.b >>= 1"));
+        assert_eq!("UnsignedRightShiftAssign", mutation("a >>>= 1"));
+        assert_eq!("BitAndAssign", mutation("a &= 1"));
+        assert_eq!("BitOrAssign", mutation("a[0] |= 1"));
+        assert_eq!("BitXorAssign", mutation("a ^= 1"));
+        assert_eq!("AndAssign", mutation("a &&= b"));
+        assert_eq!("OrAssign", mutation("a ||= b"));
+        assert_eq!("(= a (>= b 1))", tree("a = b >= 1"));
+    }
+
+    #[test]
+    fn compound_assignment_is_no_operator() {
+        assert_eq!(" *= 2", Expr::parse("f() *= 2").unwrap().0);
+        assert_eq!("(<= a 1)", tree("a <= 1"));
     }
 
     #[test]
@@ -1,25 +1,30 @@
 use crate::parse::{
     char_ws,
+    error::{CollectErrors, SyntaxError},
     expression::Expr,
+    ignore_ws,
     instruction::{FunctionBody, Statement},
+    keyword,
     scope::Variable,
-    tag_ws,
+    span::{LineIndex, Locate},
 };
 use nom::{
+    branch::alt,
+    combinator::{map, opt},
     sequence::{delimited, preceded, separated_pair},
     IResult,
 };
 
 #[derive(Debug)]
 pub struct ForLoop {
-    condition: ForLoopCondition,
-    body: FunctionBody,
+    pub condition: ForLoopCondition,
+    pub body: FunctionBody,
 }
 
 impl ForLoop {
     pub fn parse(input: &str) -> IResult<&str, ForLoop> {
         let (input, condition) = preceded(
-            tag_ws("for"),
+            keyword("for"),
             delimited(char_ws('('), ForLoopCondition::parse, char_ws(')')),
         )(input)?;
 
@@ -39,55 +44,270 @@ impl ForLoop {
 pub enum ForLoopCondition {
     // for(;;)
     CStyle {
-        // This type of JavaScript only allows let as start of for loops
-        // TODO Allow this to be optional
-        prerequisite: Variable,
-        condition: Box<Expr>,
-        mutation: Box<Expr>,
+        prerequisite: Option<Prerequisite>,
+        /// Loops without condition run until `break`
+        condition: Option<Box<Expr>>,
+        mutation: Option<Box<Expr>>,
     },
 
-    // TODO implement
     // for(let x of y)
     ElemOfIter {
-        element: String,
-        iter: String,
+        element: LoopTarget,
+        iter: Box<Expr>,
     },
     // for(let x in y)
     KeyInIter {
-        key: String,
-        iter: String,
+        key: LoopTarget,
+        iter: Box<Expr>,
     },
 }
 
+/// Left side of `for...of` and `for...in`
+#[derive(Debug)]
+pub enum LoopTarget {
+    /// `let x`, declared by the loop
+    Declaration(Variable),
+    /// Variable or property declared elsewhere, like `x` or `o.k`
+    Assignment(Box<Expr>),
+}
+
+/// First clause of a C-style for loop
+#[derive(Debug)]
+pub enum Prerequisite {
+    /// `let i = 0, j = n`
+    Declaration(Vec<Variable>),
+    /// `i = 0`
+    Expression(Box<Expr>),
+}
+
 impl ForLoopCondition {
     fn parse(input: &str) -> IResult<&str, ForLoopCondition> {
-        ForLoopCondition::parse_c_style(input)
+        alt((
+            ForLoopCondition::parse_iteration,
+            ForLoopCondition::parse_c_style,
+        ))(input)
+    }
+
+    fn parse_iteration(input: &str) -> IResult<&str, ForLoopCondition> {
+        let (input, variable) = alt((
+            map(Variable::parse_binding, LoopTarget::Declaration),
+            map(Expr::parse_target, |target| {
+                LoopTarget::Assignment(Box::new(target))
+            }),
+        ))(input)?;
+        let (input, of) =
+            alt((map(keyword("of"), |_| true), map(keyword("in"), |_| false)))(input)?;
+        let (input, iter) = map(ignore_ws(Expr::parse), Box::new)(input)?;
+
+        let condition = if of {
+            ForLoopCondition::ElemOfIter {
+                element: variable,
+                iter,
+            }
+        } else {
+            ForLoopCondition::KeyInIter {
+                key: variable,
+                iter,
+            }
+        };
+        Ok((input, condition))
     }
 
     fn parse_c_style(input: &str) -> IResult<&str, ForLoopCondition> {
+        let clause = || opt(map(Expr::parse_sequence, Box::new));
+
         let (rest, (prerequisite, (condition, mutation))) = separated_pair(
-            Variable::parse,
+            opt(alt((
+                map(Variable::parse, Prerequisite::Declaration),
+                map(Expr::parse_sequence, |expr| {
+                    Prerequisite::Expression(Box::new(expr))
+                }),
+            ))),
             char_ws(';'),
-            separated_pair(Expr::parse, char_ws(';'), Expr::parse),
+            separated_pair(clause(), char_ws(';'), clause()),
         )(input)?;
 
         Ok((
             rest,
             ForLoopCondition::CStyle {
                 prerequisite,
-                condition: Box::new(condition),
-                mutation: Box::new(mutation),
+                condition,
+                mutation,
             },
         ))
     }
 }
 
+impl Locate for ForLoop {
+    fn locate(&mut self, lines: &LineIndex) {
+        match &mut self.condition {
+            ForLoopCondition::CStyle {
+                prerequisite,
+                condition,
+                mutation,
+            } => {
+                prerequisite.locate(lines);
+                condition.locate(lines);
+                mutation.locate(lines);
+            }
+            ForLoopCondition::ElemOfIter {
+                element: variable,
+                iter,
+            }
+            | ForLoopCondition::KeyInIter {
+                key: variable,
+                iter,
+            } => {
+                variable.locate(lines);
+                iter.locate(lines);
+            }
+        }
+        self.body.locate(lines);
+    }
+}
+
+impl Locate for LoopTarget {
+    fn locate(&mut self, lines: &LineIndex) {
+        match self {
+            LoopTarget::Declaration(variable) => variable.locate(lines),
+            LoopTarget::Assignment(target) => target.locate(lines),
+        }
+    }
+}
+
+impl Locate for Prerequisite {
+    fn locate(&mut self, lines: &LineIndex) {
+        match self {
+            Prerequisite::Declaration(variables) => variables.locate(lines),
+            Prerequisite::Expression(expr) => expr.locate(lines),
+        }
+    }
+}
+
+impl CollectErrors for ForLoop {
+    fn collect_errors(&self, errors: &mut Vec<SyntaxError>) {
+        match &self.condition {
+            ForLoopCondition::CStyle {
+                prerequisite,
+                condition,
+                mutation,
+            } => {
+                prerequisite.collect_errors(errors);
+                condition.collect_errors(errors);
+                mutation.collect_errors(errors);
+            }
+            ForLoopCondition::ElemOfIter {
+                element: target,
+                iter,
+            }
+            | ForLoopCondition::KeyInIter { key: target, iter } => {
+                if let LoopTarget::Assignment(target) = target {
+                    target.collect_errors(errors);
+                }
+                iter.collect_errors(errors);
+            }
+        }
+        self.body.collect_errors(errors);
+    }
+}
+
+impl CollectErrors for Prerequisite {
+    fn collect_errors(&self, errors: &mut Vec<SyntaxError>) {
+        match self {
+            Prerequisite::Declaration(variables) => variables.collect_errors(errors),
+            Prerequisite::Expression(expr) => expr.collect_errors(errors),
+        }
+    }
+}
+
+#[cfg(test)]
 mod tests {
     use super::*;
 
     #[test]
     fn c_style_condition() {
         assert!(ForLoopCondition::parse_c_style("let x = 1; 1; 1").is_ok());
+
+        match ForLoopCondition::parse_c_style(" ; ; ") {
+            Ok((
+                " ",
+                ForLoopCondition::CStyle {
+                    prerequisite: None,
+                    condition: None,
+                    mutation: None,
+                },
+            )) => {}
+            other => panic!("expected empty clauses, got {:?}", other),
+        }
+
+        match ForLoopCondition::parse_c_style("let i = 0, j = n; i < j; i++, j--") {
+            Ok((
+                "",
+                ForLoopCondition::CStyle {
+                    prerequisite: Some(Prerequisite::Declaration(variables)),
+                    mutation: Some(_),
+                    ..
+                },
+            )) => assert_eq!(2, variables.len()),
+            other => panic!("expected two declarations, got {:?}", other),
+        }
+
+        assert!(matches!(
+            ForLoopCondition::parse_c_style("i = 0; i < n;"),
+            Ok((
+                "",
+                ForLoopCondition::CStyle {
+                    prerequisite: Some(Prerequisite::Expression(_)),
+                    mutation: None,
+                    ..
+                }
+            ))
+        ));
+    }
+
+    #[test]
+    fn iteration_condition() {
+        use crate::parse::scope::DeclarationKind;
+
+        match ForLoopCondition::parse("const x of Object.entries(y)") {
+            Ok((
+                "",
+                ForLoopCondition::ElemOfIter {
+                    element: LoopTarget::Declaration(element),
+                    ..
+                },
+            )) => {
+                assert_eq!("x", element.identifier.0);
+                assert_eq!(DeclarationKind::Const, element.kind);
+            }
+            other => panic!("expected for ... of, got {:?}", other),
+        }
+
+        assert!(matches!(
+            ForLoopCondition::parse("let key in object"),
+            Ok(("", ForLoopCondition::KeyInIter { .. }))
+        ));
+        assert!(matches!(
+            ForLoopCondition::parse("let offset = 0; offset < 1; offset++"),
+            Ok(("", ForLoopCondition::CStyle { .. }))
+        ));
+        assert!(ForLoopCondition::parse_iteration("let x = 1 of y").is_err());
+
+        assert!(matches!(
+            ForLoopCondition::parse("o.k of list"),
+            Ok((
+                "",
+                ForLoopCondition::ElemOfIter {
+                    element: LoopTarget::Assignment(_),
+                    ..
+                }
+            ))
+        ));
+        assert!(matches!(
+            ForLoopCondition::parse("x in object"),
+            Ok(("", ForLoopCondition::KeyInIter { .. }))
+        ));
+        assert!(ForLoopCondition::parse_iteration("f() of list").is_err());
     }
 
     #[test]
@@ -95,6 +315,9 @@ mod tests {
         let cases = vec![
             "for (let i = 1; 1; 1) { return 1 }",
             "for (let i = 1; 1; 1) 1",
+            "for (;;) break",
+            "for (let x of xs) { x }",
+            "for (var k in {a: 1}) k",
         ];
 
         for case in cases {
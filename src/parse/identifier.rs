@@ -1,22 +1,23 @@
 use crate::parse::keywords::is_keyword;
 use crate::parse::*;
-use nom::character::complete::alpha1;
 use nom::IResult;
 
 #[derive(Debug, Eq, PartialEq, PartialOrd, Ord, Clone, Hash)]
-pub struct Identifier(String);
+pub struct Identifier(pub String);
 
 impl Identifier {
     /// Recognize Identifiers,
     /// Escapes keywords
     pub fn parse(input: &str) -> IResult<&str, Identifier> {
-        let (rest, identifier) = alpha1(input)?;
+        let (rest, identifier) = name(input)
+            .inspect_err(|_| error::expected(input, error::Expected::Label("identifier")))?;
 
-        if is_keyword(identifier) {
+        if is_keyword(&identifier) {
+            error::expected(input, error::Expected::Label("identifier"));
             return Err(nom::Err::Error((input, nom::error::ErrorKind::Tag)));
         }
 
-        Ok((rest, Identifier(identifier.to_string())))
+        Ok((rest, Identifier(identifier)))
     }
 
     /// Recognize Identifiers,
@@ -25,4 +26,148 @@ impl Identifier {
     pub fn parse_ws(input: &str) -> IResult<&str, Identifier> {
         ignore_ws(Identifier::parse)(input)
     }
+
+    /// Recognize property names, which may be keywords like `o.default`,
+    /// Ignore Whitespace
+    pub fn parse_name_ws(input: &str) -> IResult<&str, Identifier> {
+        let (input, _) = whitespace(input)?;
+        let (rest, name) = name(input)
+            .inspect_err(|_| error::expected(input, error::Expected::Label("property name")))?;
+        Ok((rest, Identifier(name)))
+    }
+}
+
+/// Characters an identifier may start with.
+/// `char::is_alphabetic` stands in for Unicode's ID_Start.
+pub fn is_id_start(c: char) -> bool {
+    c == '$' || c == '_' || c.is_alphabetic()
+}
+
+/// Characters an identifier may continue with.
+/// Stands in for Unicode's ID_Continue, including the joiners and common combining marks.
+pub fn is_id_continue(c: char) -> bool {
+    is_id_start(c)
+        || c.is_alphanumeric()
+        || c == '\u{200C}'
+        || c == '\u{200D}'
+        || matches!(c,
+            '\u{0300}'..='\u{036F}'
+            | '\u{1AB0}'..='\u{1AFF}'
+            | '\u{1DC0}'..='\u{1DFF}'
+            | '\u{20D0}'..='\u{20FF}'
+            | '\u{FE20}'..='\u{FE2F}')
+}
+
+/// IdentifierName, including keywords.
+/// Escapes like `\u0061` or `\u{61}` are resolved.
+fn name(input: &str) -> IResult<&str, String> {
+    let mut name = String::new();
+    let mut rest = input;
+
+    loop {
+        let (c, after) = match rest.strip_prefix("\\u") {
+            Some(escape) => match unicode_escape(escape)
+                .and_then(|(code, after)| Some((std::char::from_u32(code)?, after)))
+            {
+                Some(escaped) => escaped,
+                None => return Err(nom::Err::Error((rest, nom::error::ErrorKind::Escaped))),
+            },
+            None => match rest.chars().next() {
+                Some(c) => (c, &rest[c.len_utf8()..]),
+                None => break,
+            },
+        };
+
+        let valid = if name.is_empty() {
+            is_id_start(c)
+        } else {
+            is_id_continue(c)
+        };
+
+        if !valid {
+            // Escapes always need to result in a valid character
+            if rest.starts_with('\\') {
+                return Err(nom::Err::Error((rest, nom::error::ErrorKind::Escaped)));
+            }
+            break;
+        }
+
+        name.push(c);
+        rest = after;
+    }
+
+    if name.is_empty() {
+        return Err(nom::Err::Error((input, nom::error::ErrorKind::Alpha)));
+    }
+
+    Ok((rest, name))
+}
+
+/// Code point of `XXXX` or `{X...}`, following a `\u`.
+/// Might be a lone surrogate.
+pub fn unicode_escape(input: &str) -> Option<(u32, &str)> {
+    let (digits, rest) = match input.strip_prefix('{') {
+        Some(braced) => {
+            let end = braced.find('}')?;
+            (&braced[..end], &braced[end + 1..])
+        }
+        None => (input.get(..4)?, &input[4..]),
+    };
+
+    if digits.is_empty() || !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+
+    let code = u32::from_str_radix(digits, 16).ok()?;
+    if code > 0x10FFFF {
+        return None;
+    }
+    Some((code, rest))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Identifier;
+
+    fn parse(input: &str) -> Result<(&str, String), ()> {
+        Identifier::parse(input)
+            .map(|(rest, identifier)| (rest, identifier.0))
+            .map_err(|_| ())
+    }
+
+    #[test]
+    fn names() {
+        assert_eq!(Ok(("", "player2".to_string())), parse("player2"));
+        assert_eq!(Ok(("", "_private".to_string())), parse("_private"));
+        assert_eq!(Ok((".x", "$el".to_string())), parse("$el.x"));
+        assert_eq!(Ok((" = 1", "café".to_string())), parse("café = 1"));
+        assert_eq!(Ok(("", "cafe\u{301}".to_string())), parse("cafe\u{301}"));
+        assert!(parse("2fast").is_err());
+    }
+
+    #[test]
+    fn property_names() {
+        let name = |input| Identifier::parse_name_ws(input).map(|(rest, name)| (rest, name.0));
+        assert_eq!(Ok(("", "default".to_string())), name(" default"));
+        assert_eq!(Ok(("(", "catch".to_string())), name("catch("));
+        assert!(Identifier::parse("default").is_err());
+        assert!(name("2fast").is_err());
+    }
+
+    #[test]
+    fn escapes() {
+        assert_eq!(Ok(("", "abc".to_string())), parse("\\u0061bc"));
+        assert_eq!(Ok(("", "ab".to_string())), parse("a\\u{62}"));
+        assert!(parse("\\u0020").is_err());
+        assert!(parse("\\u00").is_err());
+    }
+
+    #[test]
+    fn keywords() {
+        assert!(parse("if").is_err());
+        assert!(parse("\\u0069f").is_err());
+        assert_eq!(Ok(("", "iffy".to_string())), parse("iffy"));
+        assert_eq!(Ok(("", "letter".to_string())), parse("letter"));
+        assert_eq!(Ok(("", "of".to_string())), parse("of"));
+    }
 }
@@ -1,39 +1,58 @@
-use crate::parse::{char_ws, expression::Expr, for_loop::ForLoop, scope::*, tag_ws};
+use crate::parse::{
+    char_ws,
+    comment::{self, Comment},
+    error::{self, CollectErrors},
+    expression::Expr,
+    for_loop::ForLoop,
+    identifier::Identifier,
+    keyword,
+    scope::*,
+    span::{LineIndex, Locate, Span},
+    spanned,
+    switch::Switch,
+    whitespace,
+};
 use nom::{
     combinator::opt,
-    multi::many0,
-    sequence::{delimited, preceded},
+    sequence::{delimited, pair, preceded, terminated},
     IResult,
 };
-///
-/// Definitions
-///
-/// The Scope of JavaScript may Include several Definitions
-///
-/// starting with
-///
-/// let <ident>;
-/// let <ident> = <expr>;
-/// function <ident> ( <list(',', <expr>)>) { ... }
+//
+// Definitions
+//
+// The Scope of JavaScript may Include several Definitions
+//
+// starting with
+//
+// let <ident>;
+// let <ident> = <expr>;
+// function <ident> ( <list(',', <expr>)>) { ... }
 
 /// List of Variable definitions, expressions, if/else pairs, for/whiles and return statements
+///
+/// Functions are hoisted, so their order does not matter.
+/// Variable definitions are kept in between the other statements,
+/// as they get initialized at the exact place they are written down.
 #[derive(Debug)]
 pub struct FunctionBody {
-    pub scope: Vec<Variable>,
     pub functions: Vec<Function>,
     pub instructions: Vec<Statement>,
+    /// Comments after the last statement, only kept on request
+    pub comments: Vec<Comment>,
+    pub span: Span,
 }
 
 impl FunctionBody {
     pub fn parse(input: &str) -> IResult<&str, FunctionBody> {
         enum FbItem {
-            Var(Variable),
+            Var(Vec<Variable>, Span),
             Statement(Statement),
             Function(Function),
         }
         fn parse_fb_item(input: &str) -> IResult<&str, FbItem> {
-            if let Ok((i, v)) = Variable::parse(input) {
-                return Ok((i, FbItem::Var(v)));
+            if let Ok((i, (v, span))) = spanned(Variable::parse)(input) {
+                let (i, _) = terminator(i)?;
+                return Ok((i, FbItem::Var(v, span)));
             }
 
             if let Ok((i, f)) = Function::parse(input) {
@@ -45,23 +64,42 @@ impl FunctionBody {
             Ok((i, FbItem::Statement(s)))
         }
 
-        let (input, list) = many0(parse_fb_item)(input)?;
+        // Not `spanned`, as the leading comments belong to the first item
+        let (start, _) = whitespace(input)?;
+        let (input, list) = error::many_recovering(
+            pair(comment::leading, error::label("statement", parse_fb_item)),
+            |error, span| {
+                let statement = Statement {
+                    kind: StatementKind::Error(error),
+                    comments: Vec::new(),
+                    span,
+                };
+                (Vec::new(), FbItem::Statement(statement))
+            },
+        )(input)?;
+        let span = Span::between(start, if list.is_empty() { start } else { input });
+        let (input, comments) = comment::leading(input)?;
         let fb = list.into_iter().fold(
             FunctionBody {
-                scope: Vec::new(),
                 functions: Vec::new(),
                 instructions: Vec::new(),
+                comments,
+                span,
             },
-            |mut acc, vs| {
+            |mut acc, (comments, vs)| {
                 match vs {
-                    FbItem::Var(v) => {
-                        acc.scope.push(v);
+                    FbItem::Var(v, span) => {
+                        acc.instructions.push(Statement {
+                            span,
+                            kind: StatementKind::Declaration(v),
+                            comments,
+                        });
                     }
                     FbItem::Statement(s) => {
-                        acc.instructions.push(s);
+                        acc.instructions.push(Statement { comments, ..s });
                     }
                     FbItem::Function(f) => {
-                        acc.functions.push(f);
+                        acc.functions.push(Function { comments, ..f });
                     }
                 };
 
@@ -91,6 +129,60 @@ mod statement_tests {
         assert!(Statement::parse_continue("\n  continue ").is_ok());
     }
 
+    #[test]
+    fn labels() {
+        use super::StatementKind;
+
+        let (_, statement) = Statement::parse("outer: for (;;) { continue outer }").unwrap();
+        match statement.kind {
+            StatementKind::Labeled { label, body } => {
+                assert_eq!("outer", label.0);
+                assert!(matches!(body.kind, StatementKind::For(_)));
+            }
+            other => panic!("expected a labeled statement, got {:?}", other),
+        }
+
+        assert!(matches!(
+            Statement::parse("break outer").unwrap().1.kind,
+            StatementKind::Break(Some(_))
+        ));
+        let (rest, statement) = Statement::parse("continue\nouter").unwrap();
+        assert!(matches!(statement.kind, StatementKind::Continue(None)));
+        assert_eq!("\nouter", rest);
+
+        assert!(matches!(
+            Statement::parse("{ x: 1 }").unwrap().1.kind,
+            StatementKind::Block(_)
+        ));
+    }
+
+    #[test]
+    fn try_statement() {
+        use super::StatementKind;
+
+        let (_, statement) =
+            Statement::parse("try { f() } catch (e) { g(e) } finally { h() }").unwrap();
+        match statement.kind {
+            StatementKind::Try {
+                catch: Some(catch),
+                finally: Some(_),
+                ..
+            } => assert_eq!("e", catch.parameter.unwrap().0),
+            other => panic!("expected a try statement, got {:?}", other),
+        }
+
+        assert!(Statement::parse_try("try {} catch {}").is_ok());
+        assert!(Statement::parse_try("try {} finally {}").is_ok());
+        assert!(Statement::parse_try("try {}").is_err());
+        assert!(Statement::parse_try("try f()\ncatch {}").is_err());
+    }
+
+    #[test]
+    fn throw() {
+        assert!(Statement::parse("throw new_error('oops')").is_ok());
+        assert!(Statement::parse("throw\nx").is_err());
+    }
+
     #[test]
     fn single_statement() {
         assert!(Statement::single_statement_body("return").is_ok());
@@ -100,9 +192,18 @@ mod statement_tests {
 
     #[test]
     fn expression() {
+        use super::StatementKind;
         assert!(Statement::parse_expression("x*x").is_ok());
         assert!(Statement::parse_expression("1+1").is_ok());
         assert!(Statement::parse_expression("!x?y:z").is_ok());
+        assert!(matches!(
+            Statement::parse_expression("a++, b++"),
+            Ok(("", StatementKind::Expression(_)))
+        ));
+        assert!(matches!(
+            Statement::parse_return("return a, b"),
+            Ok(("", StatementKind::Return(Some(_))))
+        ));
     }
 
     #[test]
@@ -113,6 +214,17 @@ mod statement_tests {
         assert!(Statement::parse_while(input).is_ok());
     }
 
+    #[test]
+    fn do_while() {
+        assert!(Statement::parse_do_while("do x++; while (x < 3)").is_ok());
+        assert!(Statement::parse_do_while("do { x++ } while (x < 3) x").is_ok());
+        assert_eq!(
+            " x",
+            Statement::parse_do_while("do {} while (x); x").unwrap().0
+        );
+        assert!(Statement::parse_do_while("do while (x)").is_err());
+    }
+
     #[test]
     fn test_if() {
         let inputs = vec![
@@ -130,7 +242,16 @@ mod statement_tests {
 /// Either an Expression, if/else pair, for/while loop or return statement
 /// Note that Mutations are expressions
 #[derive(Debug)]
-pub enum Statement {
+pub struct Statement {
+    pub kind: StatementKind,
+    /// Comments right before the statement, only kept on request
+    pub comments: Vec<Comment>,
+    pub span: Span,
+}
+
+#[derive(Debug)]
+pub enum StatementKind {
+    Declaration(Vec<Variable>),
     Return(Option<Box<Expr>>),
     If {
         condition: Box<Expr>,
@@ -141,46 +262,106 @@ pub enum Statement {
         condition: Box<Expr>,
         body: FunctionBody,
     },
+    /// Loop running its body at least once
+    DoWhile {
+        body: FunctionBody,
+        condition: Box<Expr>,
+    },
+    /// Statements in curly brackets
+    Block(FunctionBody),
     For(ForLoop),
-    Break,
-    Continue,
+    Switch(Switch),
+    /// Statement `break` and `continue` may refer to by its label, like
+    /// ```js
+    /// outer: for (const row of rows) { for (const x of row) continue outer }
+    /// ```
+    Labeled {
+        label: Identifier,
+        body: Box<Statement>,
+    },
+    Break(Option<Identifier>),
+    Continue(Option<Identifier>),
+    Throw(Box<Expr>),
+    /// Has a `catch` or a `finally` block, or both
+    Try {
+        body: FunctionBody,
+        catch: Option<Box<Catch>>,
+        finally: Option<FunctionBody>,
+    },
     Expression(Box<Expr>),
+    /// Lone `;`
+    Empty,
+    /// Statement, which could not be parsed. Only produced while recovering.
+    Error(error::SyntaxError),
+}
+
+/// `catch (error) { ... }`, the parameter may be left out
+#[derive(Debug)]
+pub struct Catch {
+    pub parameter: Option<Identifier>,
+    pub body: FunctionBody,
 }
 
 impl Statement {
     pub fn parse(input: &str) -> IResult<&str, Statement> {
         use nom::branch::alt;
-        alt((
+        let (input, (kind, span)) = spanned(alt((
+            Statement::parse_empty,
             Statement::parse_if_block,
             Statement::parse_return,
             Statement::parse_while,
+            Statement::parse_do_while,
             Statement::parse_for,
+            Statement::parse_switch,
             Statement::parse_break,
             Statement::parse_continue,
+            Statement::parse_throw,
+            Statement::parse_try,
+            Statement::parse_block,
+            Statement::parse_labeled,
             Statement::parse_expression,
-        ))(input)
+        )))(input)?;
+
+        // Compound statements end with their body
+        let (input, _) = match kind {
+            StatementKind::Return(_)
+            | StatementKind::Break(_)
+            | StatementKind::Continue(_)
+            | StatementKind::Throw(_)
+            | StatementKind::Expression(_) => terminator(input)?,
+            _ => (input, ()),
+        };
+
+        Ok((
+            input,
+            Statement {
+                kind,
+                comments: Vec::new(),
+                span,
+            },
+        ))
     }
 
-    fn parse_for(input: &str) -> IResult<&str, Statement> {
-        ForLoop::parse(input).map(|(i, f)| (i, Statement::For(f)))
+    fn parse_for(input: &str) -> IResult<&str, StatementKind> {
+        ForLoop::parse(input).map(|(i, f)| (i, StatementKind::For(f)))
     }
 
-    fn parse_expression(input: &str) -> IResult<&str, Statement> {
-        Expr::parse(input).map(|(i, e)| (i, Statement::Expression(Box::new(e))))
+    fn parse_expression(input: &str) -> IResult<&str, StatementKind> {
+        Expr::parse_sequence(input).map(|(i, e)| (i, StatementKind::Expression(Box::new(e))))
     }
 
-    fn parse_if_block(input: &str) -> IResult<&str, Statement> {
+    fn parse_if_block(input: &str) -> IResult<&str, StatementKind> {
         let (input, condition) = preceded(
-            tag_ws("if"),
-            delimited(char_ws('('), Expr::parse, char_ws(')')),
+            keyword("if"),
+            delimited(char_ws('('), Expr::parse_sequence, char_ws(')')),
         )(input)?;
 
         let (input, body) = Statement::single_statement_body(input)?;
-        if let Ok((input, _)) = tag_ws("else")(input) {
+        if let Ok((input, _)) = keyword("else")(input) {
             let (input, else_branch) = Statement::single_statement_body(input)?;
             return Ok((
                 input,
-                Statement::If {
+                StatementKind::If {
                     condition: Box::new(condition),
                     body,
                     else_branch: Some(else_branch),
@@ -190,7 +371,7 @@ impl Statement {
 
         Ok((
             input,
-            Statement::If {
+            StatementKind::If {
                 condition: Box::new(condition),
                 body,
                 else_branch: None,
@@ -198,46 +379,141 @@ impl Statement {
         ))
     }
 
-    fn parse_while(input: &str) -> IResult<&str, Statement> {
+    fn parse_while(input: &str) -> IResult<&str, StatementKind> {
         let (input, condition) = preceded(
-            tag_ws("while"),
-            delimited(char_ws('('), Expr::parse, char_ws(')')),
+            keyword("while"),
+            delimited(char_ws('('), Expr::parse_sequence, char_ws(')')),
         )(input)?;
 
         let (input, body) = Statement::single_statement_body(input)?;
 
-        return Ok((
+        Ok((
             input,
-            Statement::While {
+            StatementKind::While {
                 condition: Box::new(condition),
                 body,
             },
-        ));
+        ))
+    }
+
+    /// The `;` after a do-while loop is optional, even on the same line
+    fn parse_do_while(input: &str) -> IResult<&str, StatementKind> {
+        let (input, body) = preceded(keyword("do"), Statement::single_statement_body)(input)?;
+        let (input, condition) = preceded(
+            keyword("while"),
+            delimited(char_ws('('), Expr::parse_sequence, char_ws(')')),
+        )(input)?;
+        let (input, _) = opt(error::quiet(char_ws(';')))(input)?;
+
+        Ok((
+            input,
+            StatementKind::DoWhile {
+                body,
+                condition: Box::new(condition),
+            },
+        ))
     }
 
-    fn parse_return(input: &str) -> IResult<&str, Statement> {
-        let (input, ret) = preceded(tag_ws("return"), opt(Expr::parse))(input)?;
+    fn parse_switch(input: &str) -> IResult<&str, StatementKind> {
+        Switch::parse(input).map(|(i, s)| (i, StatementKind::Switch(s)))
+    }
 
-        if let Some(expr) = ret {
-            Ok((input, Statement::Return(Some(Box::new(expr)))))
-        } else {
-            Ok((input, Statement::Return(None)))
+    fn parse_empty(input: &str) -> IResult<&str, StatementKind> {
+        char_ws(';')(input).map(|(i, _)| (i, StatementKind::Empty))
+    }
+
+    /// `return` followed by a line break returns nothing
+    fn parse_return(input: &str) -> IResult<&str, StatementKind> {
+        let (input, _) = keyword("return")(input)?;
+        if line_break(input) {
+            return Ok((input, StatementKind::Return(None)));
         }
+
+        let (input, ret) = opt(Expr::parse_sequence)(input)?;
+        Ok((input, StatementKind::Return(ret.map(Box::new))))
     }
 
-    fn parse_break(input: &str) -> IResult<&str, Statement> {
-        tag_ws("break")(input).map(|(i, _)| (i, Statement::Break))
+    fn parse_break(input: &str) -> IResult<&str, StatementKind> {
+        let (input, _) = keyword("break")(input)?;
+        let (input, label) = Statement::parse_label(input)?;
+        Ok((input, StatementKind::Break(label)))
     }
 
-    fn parse_continue(input: &str) -> IResult<&str, Statement> {
-        tag_ws("continue")(input).map(|(i, _)| (i, Statement::Continue))
+    fn parse_continue(input: &str) -> IResult<&str, StatementKind> {
+        let (input, _) = keyword("continue")(input)?;
+        let (input, label) = Statement::parse_label(input)?;
+        Ok((input, StatementKind::Continue(label)))
+    }
+
+    /// Label after `break` or `continue`, which has to be on the same line
+    fn parse_label(input: &str) -> IResult<&str, Option<Identifier>> {
+        if line_break(input) {
+            return Ok((input, None));
+        }
+        opt(error::quiet(Identifier::parse_ws))(input)
+    }
+
+    /// Unlike `return`, `throw` can't be followed by a line break
+    fn parse_throw(input: &str) -> IResult<&str, StatementKind> {
+        let (input, _) = keyword("throw")(input)?;
+        if line_break(input) {
+            error::expected(input, error::Expected::Label("expression"));
+            return Err(nom::Err::Error((input, nom::error::ErrorKind::Verify)));
+        }
+
+        let (input, value) = Expr::parse_sequence(input)?;
+        Ok((input, StatementKind::Throw(Box::new(value))))
+    }
+
+    fn parse_try(input: &str) -> IResult<&str, StatementKind> {
+        let (input, body) = preceded(keyword("try"), block)(input)?;
+        let (input, catch) = opt(preceded(
+            keyword("catch"),
+            pair(
+                opt(delimited(char_ws('('), Identifier::parse_ws, char_ws(')'))),
+                block,
+            ),
+        ))(input)?;
+        let catch = catch.map(|(parameter, body)| Box::new(Catch { parameter, body }));
+
+        let (input, finally) = match preceded(keyword("finally"), block)(input) {
+            Ok((input, finally)) => (input, Some(finally)),
+            Err(_) if catch.is_some() => (input, None),
+            Err(error) => return Err(error),
+        };
+
+        Ok((
+            input,
+            StatementKind::Try {
+                body,
+                catch,
+                finally,
+            },
+        ))
+    }
+
+    fn parse_block(input: &str) -> IResult<&str, StatementKind> {
+        block(input).map(|(i, body)| (i, StatementKind::Block(body)))
+    }
+
+    fn parse_labeled(input: &str) -> IResult<&str, StatementKind> {
+        let (input, label) = terminated(Identifier::parse_ws, error::quiet(char_ws(':')))(input)?;
+        let (input, body) = Statement::parse(input)?;
+        Ok((
+            input,
+            StatementKind::Labeled {
+                label,
+                body: Box::new(body),
+            },
+        ))
     }
 
     fn into_function_body(self) -> FunctionBody {
         FunctionBody {
-            scope: Vec::new(),
             functions: Vec::new(),
+            span: self.span,
             instructions: vec![self],
+            comments: Vec::new(),
         }
     }
 
@@ -254,10 +530,146 @@ impl Statement {
     /// }
     /// ```
     pub fn single_statement_body(input: &str) -> IResult<&str, FunctionBody> {
-        if let Ok((i, s)) = Statement::parse(input) {
-            Ok((i, s.into_function_body()))
-        } else {
-            delimited(char_ws('{'), FunctionBody::parse, char_ws('}'))(input)
+        let (input, statement) = Statement::parse(input)?;
+        match statement.kind {
+            StatementKind::Block(body) => Ok((input, body)),
+            _ => Ok((input, statement.into_function_body())),
+        }
+    }
+}
+
+/// Statements in curly brackets
+pub fn block(input: &str) -> IResult<&str, FunctionBody> {
+    let (input, (body, span)) =
+        spanned(delimited(char_ws('{'), FunctionBody::parse, char_ws('}')))(input)?;
+    Ok((input, FunctionBody { span, ..body }))
+}
+
+/// Whether a line break comes before the next token
+fn line_break(input: &str) -> bool {
+    whitespace(input).is_ok_and(|(_, trivia)| trivia.contains('\n'))
+}
+
+/// End of a simple statement.
+///
+/// Either an explicit `;`, or one inserted automatically
+/// before a line break, a closing `}` or the end of input.
+/// Nothing but the `;` gets consumed.
+fn terminator(input: &str) -> IResult<&str, ()> {
+    if let Ok((rest, _)) = error::quiet(char_ws(';'))(input) {
+        return Ok((rest, ()));
+    }
+
+    let (next, trivia) = whitespace(input)?;
+    if next.is_empty() || next.starts_with('}') || trivia.contains('\n') {
+        return Ok((input, ()));
+    }
+
+    error::expected(next, error::Expected::Char(';'));
+    Err(nom::Err::Error((next, nom::error::ErrorKind::Char)))
+}
+
+impl Locate for FunctionBody {
+    fn locate(&mut self, lines: &LineIndex) {
+        self.span.locate(lines);
+        self.functions.locate(lines);
+        self.instructions.locate(lines);
+        self.comments.locate(lines);
+    }
+}
+
+impl Locate for Statement {
+    fn locate(&mut self, lines: &LineIndex) {
+        self.span.locate(lines);
+        self.comments.locate(lines);
+        match &mut self.kind {
+            StatementKind::Declaration(variables) => variables.locate(lines),
+            StatementKind::Return(value) => value.locate(lines),
+            StatementKind::If {
+                condition,
+                body,
+                else_branch,
+            } => {
+                condition.locate(lines);
+                body.locate(lines);
+                else_branch.locate(lines);
+            }
+            StatementKind::While { condition, body }
+            | StatementKind::DoWhile { body, condition } => {
+                condition.locate(lines);
+                body.locate(lines);
+            }
+            StatementKind::Block(body) => body.locate(lines),
+            StatementKind::For(for_loop) => for_loop.locate(lines),
+            StatementKind::Switch(switch) => switch.locate(lines),
+            StatementKind::Labeled { body, .. } => body.locate(lines),
+            StatementKind::Throw(value) => value.locate(lines),
+            StatementKind::Try {
+                body,
+                catch,
+                finally,
+            } => {
+                body.locate(lines);
+                if let Some(catch) = catch {
+                    catch.body.locate(lines);
+                }
+                finally.locate(lines);
+            }
+            StatementKind::Expression(expr) => expr.locate(lines),
+            StatementKind::Error(error) => error.locate(lines),
+            StatementKind::Break(_) | StatementKind::Continue(_) | StatementKind::Empty => {}
+        }
+    }
+}
+
+impl CollectErrors for FunctionBody {
+    fn collect_errors(&self, errors: &mut Vec<error::SyntaxError>) {
+        self.functions.collect_errors(errors);
+        self.instructions.collect_errors(errors);
+    }
+}
+
+impl CollectErrors for Statement {
+    fn collect_errors(&self, errors: &mut Vec<error::SyntaxError>) {
+        match &self.kind {
+            StatementKind::Declaration(variables) => variables.collect_errors(errors),
+            StatementKind::Return(value) => value.collect_errors(errors),
+            StatementKind::If {
+                condition,
+                body,
+                else_branch,
+            } => {
+                condition.collect_errors(errors);
+                body.collect_errors(errors);
+                else_branch.collect_errors(errors);
+            }
+            StatementKind::While { condition, body } => {
+                condition.collect_errors(errors);
+                body.collect_errors(errors);
+            }
+            StatementKind::DoWhile { body, condition } => {
+                body.collect_errors(errors);
+                condition.collect_errors(errors);
+            }
+            StatementKind::Block(body) => body.collect_errors(errors),
+            StatementKind::For(for_loop) => for_loop.collect_errors(errors),
+            StatementKind::Switch(switch) => switch.collect_errors(errors),
+            StatementKind::Labeled { body, .. } => body.collect_errors(errors),
+            StatementKind::Throw(value) => value.collect_errors(errors),
+            StatementKind::Try {
+                body,
+                catch,
+                finally,
+            } => {
+                body.collect_errors(errors);
+                if let Some(catch) = catch {
+                    catch.body.collect_errors(errors);
+                }
+                finally.collect_errors(errors);
+            }
+            StatementKind::Expression(expr) => expr.collect_errors(errors),
+            StatementKind::Error(error) => errors.push(error.clone()),
+            StatementKind::Break(_) | StatementKind::Continue(_) | StatementKind::Empty => {}
         }
     }
 }
@@ -311,4 +723,11 @@ mod function_body_tests {
 
         assert!(result.is_ok());
     }
+
+    #[test]
+    fn arrow_function_statement() {
+        let (rest, body) = FunctionBody::parse("let f = () => 5; return f()").unwrap();
+        assert_eq!("", rest);
+        assert_eq!(2, body.instructions.len());
+    }
 }
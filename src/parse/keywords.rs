@@ -1,29 +1,50 @@
 ///
 /// Keywords
 ///
-/// JavaScript contains several keywords.
-/// This will only be a subset.
+/// Reserved words of JavaScript, which can not be used as identifiers.
+/// Contextual keywords like `of` are valid identifiers.
+const KEYWORDS: &[&str] = &[
+    "await",
+    "break",
+    "case",
+    "catch",
+    "class",
+    "const",
+    "continue",
+    "debugger",
+    "default",
+    "delete",
+    "do",
+    "else",
+    "enum",
+    "export",
+    "extends",
+    "false",
+    "finally",
+    "for",
+    "function",
+    "if",
+    "import",
+    "in",
+    "instanceof",
+    "let",
+    "new",
+    "null",
+    "return",
+    "super",
+    "switch",
+    "this",
+    "throw",
+    "true",
+    "try",
+    "typeof",
+    "var",
+    "void",
+    "while",
+    "with",
+    "yield",
+];
 
 pub fn is_keyword(input: &str) -> bool {
-    vec![
-        "let",
-        "for",
-        "while",
-        "if",
-        "else",
-        "function",
-        "return",
-        "break",
-        // following are not used yet
-        "do",
-        "switch",
-        "typeof",
-        "of",
-        "in",
-        "const",
-        "var",
-        "class",
-        "constructor",
-    ]
-    .contains(&input)
+    KEYWORDS.contains(&input)
 }
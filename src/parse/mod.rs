@@ -1,3 +1,5 @@
+pub mod comment;
+pub mod error;
 pub mod expression;
 pub mod for_loop;
 pub mod identifier;
@@ -5,16 +7,91 @@ pub mod instruction;
 pub mod keywords;
 pub mod obj;
 pub mod scope;
+pub mod span;
 pub mod string_template;
+pub mod switch;
 mod util;
 
 use util::*;
 
 pub type Ast = instruction::FunctionBody;
 
-#[inline]
+/// Parse a whole script.
+/// All nodes of the Ast carry their location in `source_code`.
 pub fn parse(source_code: &str) -> nom::IResult<&str, instruction::FunctionBody> {
-    instruction::FunctionBody::parse(source_code)
+    use span::Locate;
+    error::reset();
+    let (rest, mut ast) = instruction::FunctionBody::parse(source_code)?;
+    ast.locate(&span::LineIndex::new(source_code));
+    Ok((rest, ast))
+}
+
+/// Parse a whole script like `parse`,
+/// but keep all comments attached to the statement or function following them.
+pub fn parse_with_comments(source_code: &str) -> nom::IResult<&str, instruction::FunctionBody> {
+    comment::keeping(|| parse(source_code))
+}
+
+/// Parse a whole script, failing unless all of `source_code` is recognized.
+pub fn parse_complete(source_code: &str) -> Result<Ast, error::SyntaxError> {
+    match parse(source_code) {
+        Ok((rest, ast)) if whitespace(rest).is_ok_and(|(rest, _)| rest.is_empty()) => Ok(ast),
+        Ok((rest, _)) => Err(error::SyntaxError::at_furthest(source_code, rest)),
+        Err(nom::Err::Error((rest, _))) | Err(nom::Err::Failure((rest, _))) => {
+            Err(error::SyntaxError::at_furthest(source_code, rest))
+        }
+        Err(nom::Err::Incomplete(_)) => Err(error::SyntaxError::at_furthest(source_code, "")),
+    }
+}
+
+/// Parse a whole script, recovering from syntax errors.
+/// Broken statements are kept as `StatementKind::Error`,
+/// all errors are returned in order of appearance.
+pub fn parse_recovering(source_code: &str) -> (Ast, Vec<error::SyntaxError>) {
+    use error::{CollectErrors, Expected, SyntaxError};
+    use instruction::{FunctionBody, Statement, StatementKind};
+    use span::{Locate, Span};
+
+    error::reset();
+    let mut ast = error::recovering(|| {
+        let (mut rest, mut ast) =
+            FunctionBody::parse(source_code).expect("recovering bodies never fail");
+
+        // Only a stray `}` or switch clause stops the toplevel body early
+        loop {
+            let (token, _) = whitespace(rest).unwrap();
+            let stray = match token.strip_prefix('}').or_else(|| error::clause(token)) {
+                Some(stray) => stray,
+                None => break,
+            };
+
+            ast.instructions.push(Statement {
+                kind: StatementKind::Error(SyntaxError::unexpected(
+                    token,
+                    Expected::Label("statement"),
+                )),
+                comments: Vec::new(),
+                span: Span::between(token, stray),
+            });
+
+            let (next, body) = FunctionBody::parse(stray).expect("recovering bodies never fail");
+            ast.functions.extend(body.functions);
+            ast.instructions.extend(body.instructions);
+            ast.comments.extend(body.comments);
+            ast.span = ast.span.to(body.span);
+            rest = next;
+        }
+
+        ast
+    });
+
+    ast.locate(&span::LineIndex::new(source_code));
+    let mut errors = Vec::new();
+    ast.collect_errors(&mut errors);
+    // Functions are hoisted, which mixes up the order
+    errors.sort_by_key(|error| error.position.offset);
+
+    (ast, errors)
 }
 
 #[cfg(test)]
@@ -60,6 +137,115 @@ mod toplevel_tests {
         assert_eq!("", left);
     }
 
+    #[test]
+    fn spans() {
+        use super::instruction::StatementKind;
+
+        let input = "let x = 1 +\n  2\nfunction f() {\n  return x\n}\nx += 1";
+        let (_, ast) = parse(input).unwrap();
+
+        let declaration = &ast.instructions[0];
+        assert_eq!("1:1..2:4", format!("{:?}", declaration.span));
+        match &declaration.kind {
+            StatementKind::Declaration(variables) => {
+                let assign = variables[0].assign.as_ref().unwrap();
+                assert_eq!((8, 15), (assign.span.start.offset, assign.span.end.offset));
+                assert_eq!("1:9..2:4", format!("{:?}", assign.span));
+            }
+            _ => panic!("expected a declaration"),
+        }
+
+        let function = &ast.functions[0];
+        assert_eq!("3:1..5:2", format!("{:?}", function.span));
+        assert_eq!(
+            "4:3..4:11",
+            format!("{:?}", function.body.instructions[0].span)
+        );
+
+        assert_eq!("6:1..6:7", format!("{:?}", ast.instructions[1].span));
+    }
+
+    #[test]
+    fn comments() {
+        let input = "
+            // Square a number
+            function square(x) {
+                return x * /* twice */ x // done
+            }
+
+            /* the answer */
+            let answer = square(6) // 36
+            // end
+        ";
+
+        assert!(super::parse_complete(input).is_ok());
+        assert!(parse(input).unwrap().1.functions[0].comments.is_empty());
+
+        let (_, ast) = super::parse_with_comments(input).unwrap();
+        let texts = |comments: &[super::comment::Comment]| -> Vec<String> {
+            comments.iter().map(|c| c.text.clone()).collect()
+        };
+        let square = &ast.functions[0];
+        assert_eq!(vec![" Square a number"], texts(&square.comments));
+        assert_eq!(vec![" done"], texts(&square.body.comments));
+        assert_eq!(vec![" the answer "], texts(&ast.instructions[0].comments));
+        assert_eq!(vec![" 36", " end"], texts(&ast.comments));
+        assert_eq!("2:13..2:31", format!("{:?}", square.comments[0].span));
+    }
+
+    #[test]
+    fn semicolons() {
+        use super::instruction::StatementKind;
+
+        let ast = super::parse_complete("let x = 1; let y = 2; x = y;;").unwrap();
+        assert_eq!(4, ast.instructions.len());
+        assert!(matches!(ast.instructions[3].kind, StatementKind::Empty));
+
+        let ast = super::parse_complete("if (x) y = 1; else y = 2").unwrap();
+        assert_eq!(1, ast.instructions.len());
+
+        let ast = super::parse_complete("for (let i = 0; i < 3; i++) ;").unwrap();
+        assert_eq!(1, ast.instructions.len());
+    }
+
+    #[test]
+    fn automatic_semicolons() {
+        let ast = super::parse_complete("let x = 1\nx = 2 // two\nif (x) { x = 3 }").unwrap();
+        assert_eq!(3, ast.instructions.len());
+
+        let error = super::parse_complete("let x = 1 x = 2").unwrap_err();
+        assert_eq!(vec![super::error::Expected::Char(';')], error.expected);
+        assert_eq!((1, 11), (error.position.line, error.position.column));
+    }
+
+    #[test]
+    fn restricted_productions() {
+        use super::instruction::StatementKind;
+
+        let ast = super::parse_complete("function f() {\n  return\n  1\n}").unwrap();
+        let body = &ast.functions[0].body.instructions;
+        assert!(matches!(body[0].kind, StatementKind::Return(None)));
+        assert!(matches!(body[1].kind, StatementKind::Expression(_)));
+
+        assert!(super::parse_complete("let x = 1\nx++\nx").is_ok());
+        let error = super::parse_complete("let x = 1\nx\n++").unwrap_err();
+        assert_eq!(3, error.position.line);
+    }
+
+    #[test]
+    fn keyword_prefixed_identifiers() {
+        use super::instruction::StatementKind;
+
+        let ast = super::parse_complete("let letter = 1\nreturnValue = letter\nifx").unwrap();
+        assert_eq!(3, ast.instructions.len());
+        assert!(matches!(
+            ast.instructions[1].kind,
+            StatementKind::Expression(_)
+        ));
+
+        assert!(super::parse_complete("let if = 1").is_err());
+    }
+
     #[test]
     fn empty_body() {
         assert_eq!("", parse("{}").unwrap().0)
@@ -1,18 +1,20 @@
 use crate::parse::{
     char_ws, concat,
-    expression::Expr,
-    identifier::Identifier,
-    ignore_ws,
-    instruction::{FunctionBody, Statement},
+    error::{self, CollectErrors, Expected, SyntaxError},
+    expression::{Expr, ExprKind},
+    identifier::{is_id_start, Identifier},
+    instruction::{self, FunctionBody},
+    keyword,
+    span::{LineIndex, Locate, Span},
+    spanned,
     string_template::StringTemplate,
     tag_ws,
 };
 use nom::{
     branch::alt,
-    bytes::complete::tag,
-    character::complete::{char, none_of},
+    character::complete::char,
     combinator::map,
-    multi::{many0, separated_list},
+    multi::separated_list,
     sequence::{delimited, preceded, separated_pair},
     IResult,
 };
@@ -22,96 +24,120 @@ use std::collections::HashMap;
 /// Note, that this is _not_ it's final representation,
 /// just an Building Block
 #[derive(Debug)]
-pub enum Object {
+pub struct Object {
+    pub kind: ObjectKind,
+    pub span: Span,
+}
+
+#[derive(Debug)]
+pub enum ObjectKind {
+    Null,
+    Undefined,
     Boolean(bool),
     Number(f64),
+    /// Integer with `n` suffix, like `10n`
+    BigInt(i128),
     String(StringTemplate),
     Array(Vec<Expr>),
     Map(HashMap<Identifier, Expr>),
     Closure {
         args: Vec<Identifier>,
-        body: FunctionBody,
+        body: ArrowBody,
     },
 }
 
+/// Body of an arrow function
+#[derive(Debug)]
+pub enum ArrowBody {
+    /// `() => { ... }`
+    Block(FunctionBody),
+    /// `() => x`, which returns `x`
+    Expression(Box<Expr>),
+}
+
 impl Object {
     pub fn parse(input: &str) -> IResult<&str, Object> {
-        ignore_ws(alt((
-            Object::parse_bool,
-            Object::parse_number,
-            Object::parse_string,
-            Object::parse_array,
-            Object::parse_map,
-            Object::parse_closure,
-        )))(input)
+        map(
+            spanned(alt((
+                Object::parse_literal_kind,
+                Object::parse_number,
+                Object::parse_string,
+                Object::parse_array,
+                Object::parse_map,
+                Object::parse_closure,
+            ))),
+            |(kind, span)| Object { kind, span },
+        )(input)
     }
 
-    fn parse_bool(input: &str) -> IResult<&str, Object> {
-        alt((
-            map(tag("true"), |_| Object::Boolean(true)),
-            map(tag("false"), |_| Object::Boolean(false)),
-        ))(input)
+    /// Values written like identifiers, like `true`, `null` or `NaN`.
+    /// Only `true`, `false` and `null` are keywords, but the others are treated alike.
+    pub fn parse_literal(input: &str) -> IResult<&str, Object> {
+        map(spanned(Object::parse_literal_kind), |(kind, span)| Object {
+            kind,
+            span,
+        })(input)
     }
 
-    fn parse_number(input: &str) -> IResult<&str, Object> {
-        use nom::character::complete::{digit1, hex_digit1, oct_digit1};
-        use nom::number::complete::double;
-        // Integer parsing
-        map(
-            alt((
-                double,
-                map(
-                    alt((
-                        map(preceded(tag("0x"), hex_digit1), |s| {
-                            u64::from_str_radix(s, 16).unwrap()
-                        }),
-                        map(preceded(tag("0o"), oct_digit1), |s| {
-                            u64::from_str_radix(s, 8).unwrap()
-                        }),
-                        map(preceded(tag("0b"), bin_digit1), |s| {
-                            u64::from_str_radix(&s, 2).unwrap()
-                        }),
-                        map(digit1, |s: &str| s.parse::<u64>().unwrap()),
-                    )),
-                    |num: u64| num as f64,
-                ),
-            )),
-            Object::Number,
-        )(input)
+    /// Keywords standing for a value.
+    /// They are reported as part of an expression, not one by one.
+    fn parse_literal_kind(input: &str) -> IResult<&str, ObjectKind> {
+        error::quiet(alt((
+            map(keyword("true"), |_| ObjectKind::Boolean(true)),
+            map(keyword("false"), |_| ObjectKind::Boolean(false)),
+            map(keyword("null"), |_| ObjectKind::Null),
+            map(keyword("undefined"), |_| ObjectKind::Undefined),
+            map(keyword("NaN"), |_| ObjectKind::Number(f64::NAN)),
+            map(keyword("Infinity"), |_| ObjectKind::Number(f64::INFINITY)),
+        )))(input)
     }
 
-    fn parse_string(input: &str) -> IResult<&str, Object> {
-        map(StringTemplate::parse, Object::String)(input)
+    /// Numeric literal, like `42`, `.5`, `1_000`, `0xFF`, `1e-3` or `10n`
+    fn parse_number(input: &str) -> IResult<&str, ObjectKind> {
+        let (rest, kind) = match radix_prefix(input) {
+            Some((digits, radix)) => integer(digits, radix)?,
+            None => decimal(input)?,
+        };
+
+        // Numbers can't be directly followed by identifiers or digits, like `3in`
+        if rest.starts_with(|c: char| is_id_start(c) || c.is_ascii_digit() || c == '\\') {
+            return Err(nom::Err::Error((input, nom::error::ErrorKind::Digit)));
+        }
+        Ok((rest, kind))
     }
 
-    fn parse_array(input: &str) -> IResult<&str, Object> {
+    fn parse_string(input: &str) -> IResult<&str, ObjectKind> {
+        map(StringTemplate::parse, ObjectKind::String)(input)
+    }
+
+    fn parse_array(input: &str) -> IResult<&str, ObjectKind> {
         map(
             delimited(
                 char('['),
                 separated_list(char_ws(','), Expr::parse),
                 char(']'),
             ),
-            Object::Array,
+            ObjectKind::Array,
         )(input)
     }
 
-    fn parse_map(input: &str) -> IResult<&str, Object> {
+    fn parse_map(input: &str) -> IResult<&str, ObjectKind> {
         map(
             delimited(
                 char_ws('{'),
                 separated_list(
                     char_ws(','),
-                    separated_pair(Identifier::parse_ws, char_ws(':'), Expr::parse),
+                    separated_pair(Identifier::parse_name_ws, char_ws(':'), Expr::parse),
                 ),
                 char_ws('}'),
             ),
             |pairs: Vec<(Identifier, Expr)>| {
-                Object::Map(pairs.into_iter().collect::<HashMap<_, _>>())
+                ObjectKind::Map(pairs.into_iter().collect::<HashMap<_, _>>())
             },
         )(input)
     }
 
-    fn parse_closure(input: &str) -> IResult<&str, Object> {
+    fn parse_closure(input: &str) -> IResult<&str, ObjectKind> {
         use nom::sequence::tuple;
         map(
             tuple((
@@ -120,28 +146,216 @@ impl Object {
                     concat(char_ws(','), Identifier::parse_ws),
                     char_ws(')'),
                 ),
-                preceded(tag_ws("=>"), Statement::single_statement_body),
+                preceded(
+                    tag_ws("=>"),
+                    alt((
+                        map(instruction::block, ArrowBody::Block),
+                        map(Expr::parse, |expr| ArrowBody::Expression(Box::new(expr))),
+                    )),
+                ),
             )),
-            |(args, body)| Object::Closure { args, body },
+            |(args, body)| ObjectKind::Closure { args, body },
         )(input)
     }
 
     pub fn as_expr(self) -> Expr {
-        Expr::Value(self)
+        Expr {
+            span: self.span,
+            kind: ExprKind::Value(self),
+        }
+    }
+}
+
+impl Locate for Object {
+    fn locate(&mut self, lines: &LineIndex) {
+        self.span.locate(lines);
+        match &mut self.kind {
+            ObjectKind::String(template) => template.locate(lines),
+            ObjectKind::Array(elements) => elements.locate(lines),
+            ObjectKind::Map(map) => {
+                for value in map.values_mut() {
+                    value.locate(lines);
+                }
+            }
+            ObjectKind::Closure { body, .. } => match body {
+                ArrowBody::Block(body) => body.locate(lines),
+                ArrowBody::Expression(expr) => expr.locate(lines),
+            },
+            ObjectKind::Null
+            | ObjectKind::Undefined
+            | ObjectKind::Boolean(_)
+            | ObjectKind::Number(_)
+            | ObjectKind::BigInt(_) => {}
+        }
+    }
+}
+
+impl CollectErrors for Object {
+    fn collect_errors(&self, errors: &mut Vec<SyntaxError>) {
+        match &self.kind {
+            ObjectKind::String(template) => template.collect_errors(errors),
+            ObjectKind::Array(elements) => elements.collect_errors(errors),
+            ObjectKind::Map(map) => {
+                for value in map.values() {
+                    value.collect_errors(errors);
+                }
+            }
+            ObjectKind::Closure { body, .. } => match body {
+                ArrowBody::Block(body) => body.collect_errors(errors),
+                ArrowBody::Expression(expr) => expr.collect_errors(errors),
+            },
+            ObjectKind::Null
+            | ObjectKind::Undefined
+            | ObjectKind::Boolean(_)
+            | ObjectKind::Number(_)
+            | ObjectKind::BigInt(_) => {}
+        }
+    }
+}
+
+/// Digits following a `0x`, `0o` or `0b` prefix and their radix
+fn radix_prefix(input: &str) -> Option<(&str, u32)> {
+    let radix = match input.get(..2)? {
+        "0x" | "0X" => 16,
+        "0o" | "0O" => 8,
+        "0b" | "0B" => 2,
+        _ => return None,
+    };
+    Some((&input[2..], radix))
+}
+
+/// Digits of `radix`, with single `_` separators between them.
+/// The separators are removed.
+fn digits(input: &str, radix: u32) -> Option<(&str, String)> {
+    let mut digits = String::new();
+    let mut rest = input;
+
+    loop {
+        let mut chars = rest.chars();
+        match chars.next() {
+            Some(c) if c.is_digit(radix) => digits.push(c),
+            Some('_')
+                if !digits.is_empty()
+                    && chars.as_str().starts_with(|c: char| c.is_digit(radix)) => {}
+            _ => break,
+        }
+        rest = chars.as_str();
+    }
+
+    if digits.is_empty() {
+        None
+    } else {
+        Some((rest, digits))
     }
 }
 
-// TODO this is written poorly and unnessesarily allocates memory
-fn bin_digit1(input: &str) -> IResult<&str, String> {
-    use nom::{character::complete::one_of, multi::many1};
-    map(many1(one_of("01")), |list| {
-        list.into_iter().collect::<String>()
-    })(input)
+fn missing_digits(input: &str) -> nom::Err<(&str, nom::error::ErrorKind)> {
+    error::expected(input, Expected::Label("digit"));
+    nom::Err::Error((input, nom::error::ErrorKind::Digit))
+}
+
+/// Integer in `radix`, after its prefix
+fn integer(input: &str, radix: u32) -> IResult<&str, ObjectKind> {
+    let (rest, digits) = digits(input, radix).ok_or_else(|| missing_digits(input))?;
+
+    if let Some(rest) = rest.strip_prefix('n') {
+        return bigint(input, rest, &digits, radix);
+    }
+
+    // Only integers up to 2^53 are exact anyway
+    let value = u128::from_str_radix(&digits, radix)
+        .map(|n| n as f64)
+        .unwrap_or_else(|_| {
+            digits.chars().fold(0.0, |value, digit| {
+                value * radix as f64 + digit.to_digit(radix).unwrap() as f64
+            })
+        });
+    Ok((rest, ObjectKind::Number(value)))
+}
+
+fn bigint<'a>(
+    input: &'a str,
+    rest: &'a str,
+    digits: &str,
+    radix: u32,
+) -> IResult<&'a str, ObjectKind> {
+    match i128::from_str_radix(digits, radix) {
+        Ok(n) => Ok((rest, ObjectKind::BigInt(n))),
+        Err(_) => {
+            error::failed(input, Expected::Label("BigInt literal of at most 127 bits"));
+            Err(nom::Err::Failure((input, nom::error::ErrorKind::TooLarge)))
+        }
+    }
+}
+
+/// Decimal number with optional fraction and exponent
+fn decimal(input: &str) -> IResult<&str, ObjectKind> {
+    // Legacy octal literals, like `017`
+    if let Some(octal) = input.strip_prefix('0') {
+        let end = octal
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(octal.len());
+        if end > 0 && octal[..end].chars().all(|c| c.is_digit(8)) {
+            let value = u128::from_str_radix(&octal[..end], 8).map_or(f64::INFINITY, |n| n as f64);
+            return Ok((&octal[end..], ObjectKind::Number(value)));
+        }
+    }
+
+    let (mut rest, integer) = digits(input, 10).unwrap_or((input, String::new()));
+    // No separators after a leading zero, like `0_1`
+    if integer.len() > 1
+        && integer.starts_with('0')
+        && input[..input.len() - rest.len()].contains('_')
+    {
+        return Err(nom::Err::Error((input, nom::error::ErrorKind::Digit)));
+    }
+    let mut number = if integer.is_empty() {
+        "0".to_string()
+    } else {
+        integer.clone()
+    };
+    let mut integral = true;
+
+    if let Some(after) = rest.strip_prefix('.') {
+        // `1.` is a complete number, `.` is none
+        match digits(after, 10) {
+            Some((after, fraction)) => {
+                number = format!("{}.{}", number, fraction);
+                rest = after;
+            }
+            None if !integer.is_empty() => rest = after,
+            None => return Err(nom::Err::Error((input, nom::error::ErrorKind::Digit))),
+        }
+        integral = false;
+    } else if integer.is_empty() {
+        return Err(nom::Err::Error((input, nom::error::ErrorKind::Digit)));
+    }
+
+    if let Some(after) = rest.strip_prefix(['e', 'E']) {
+        let (sign, after) = match after.strip_prefix(['+', '-']) {
+            Some(unsigned) => (&after[..1], unsigned),
+            None => ("", after),
+        };
+        let (after, exponent) = digits(after, 10).ok_or_else(|| missing_digits(after))?;
+        number = format!("{}e{}{}", number, sign, exponent);
+        rest = after;
+        integral = false;
+    }
+
+    if let Some(after) = rest.strip_prefix('n') {
+        // No BigInt with fractions, exponents or leading zeros
+        if integral && (integer == "0" || !integer.starts_with('0')) {
+            return bigint(input, after, &integer, 10);
+        }
+        return Err(nom::Err::Error((input, nom::error::ErrorKind::Digit)));
+    }
+
+    Ok((rest, ObjectKind::Number(number.parse().unwrap())))
 }
 
 #[cfg(test)]
 mod tests {
-    use super::Object;
+    use super::{ArrowBody, Object, ObjectKind};
 
     #[test]
     fn parse_map() {
@@ -154,6 +368,7 @@ mod tests {
         }";
         let result = dbg!(Object::parse_map(input));
         assert_eq!("", result.unwrap().0);
+        assert_eq!("", Object::parse_map("{ default: 1, this: 2 }").unwrap().0);
     }
 
     #[test]
@@ -176,6 +391,97 @@ mod tests {
         assert!(Object::parse_number("0b101").is_ok());
     }
 
+    fn number(input: &str) -> Result<(&str, f64), ()> {
+        match Object::parse_number(input) {
+            Ok((rest, ObjectKind::Number(n))) => Ok((rest, n)),
+            _ => Err(()),
+        }
+    }
+
+    fn bigint(input: &str) -> Result<i128, ()> {
+        match Object::parse_number(input) {
+            Ok(("", ObjectKind::BigInt(n))) => Ok(n),
+            _ => Err(()),
+        }
+    }
+
+    #[test]
+    fn parse_radix() {
+        assert_eq!(Ok(("", 16.0)), number("0x10"));
+        assert_eq!(Ok(("", 255.0)), number("0XfF"));
+        assert_eq!(Ok(("", 8.0)), number("0O10"));
+        assert_eq!(Ok(("", 5.0)), number("0B101"));
+        assert_eq!(Ok(("", 2f64.powi(68))), number("0x100000000000000000"));
+        assert_eq!(
+            Ok(("", 2f64.powi(132))),
+            number("0x1000000000000000000000000000000000")
+        );
+        assert!(number("0x").is_err());
+        assert!(number("0b102").is_err());
+    }
+
+    #[test]
+    fn parse_decimal() {
+        assert_eq!(Ok(("", 0.5)), number(".5"));
+        assert_eq!(Ok(("", 1.0)), number("1."));
+        assert_eq!(Ok(("", 1500.0)), number("1.5e3"));
+        assert_eq!(Ok(("", 0.015)), number("1.5E-2"));
+        assert_eq!(Ok(("", 15.0)), number("017"));
+        assert_eq!(Ok(("", 19.0)), number("019"));
+        assert_eq!(Ok(("", 0.0)), number("0"));
+        assert_eq!(Ok((" + 1", 1.0)), number("1 + 1"));
+        assert!(number("1e").is_err());
+        assert!(number(".").is_err());
+        assert!(number("3in").is_err());
+    }
+
+    #[test]
+    fn parse_separators() {
+        assert_eq!(Ok(("", 1_000_000.0)), number("1_000_000"));
+        assert_eq!(Ok(("", 0.000_1)), number("0.000_1"));
+        assert_eq!(Ok(("", 0xFFFF.into())), number("0xFF_FF"));
+        assert!(number("1_").is_err());
+        assert!(number("1__0").is_err());
+        assert!(number("0x_1").is_err());
+        assert!(number("1_.5").is_err());
+        assert!(number("0_1").is_err());
+        assert!(number("09_1").is_err());
+        assert!(number("017_1").is_err());
+        assert_eq!(Ok(("", 0.1)), number("0.1"));
+    }
+
+    #[test]
+    fn parse_bigint() {
+        assert_eq!(Ok(123), bigint("123n"));
+        assert_eq!(Ok(0), bigint("0n"));
+        assert_eq!(Ok(255), bigint("0xFFn"));
+        assert_eq!(Ok(1_000), bigint("1_000n"));
+        assert_eq!(
+            Ok(i128::MAX),
+            bigint("170141183460469231731687303715884105727n")
+        );
+        assert!(matches!(
+            Object::parse_number("170141183460469231731687303715884105728n"),
+            Err(nom::Err::Failure(_))
+        ));
+        assert!(bigint("1.5n").is_err());
+        assert!(bigint("1e3n").is_err());
+        assert!(bigint("01n").is_err());
+    }
+
+    #[test]
+    fn parse_literal() {
+        let kind = |input| Object::parse_literal(input).map(|(rest, object)| (rest, object.kind));
+        assert!(matches!(kind("null"), Ok(("", ObjectKind::Null))));
+        assert!(matches!(
+            kind(" undefined;"),
+            Ok((";", ObjectKind::Undefined))
+        ));
+        assert!(matches!(kind("NaN"), Ok(("", ObjectKind::Number(n))) if n.is_nan()));
+        assert!(kind("nullable").is_err());
+        assert!(kind("NaNa").is_err());
+    }
+
     #[test]
     fn parse_float() {
         assert!(Object::parse_number("3.14151").is_ok());
@@ -193,11 +499,32 @@ mod tests {
 
     #[test]
     fn parse_closure() {
-        assert!(Object::parse_closure("(a, b) => return").is_ok());
+        assert!(Object::parse_closure("(a, b) => { return a }").is_ok());
+        assert!(matches!(
+            Object::parse_closure("() => 1, 2"),
+            Ok((
+                ", 2",
+                ObjectKind::Closure {
+                    body: ArrowBody::Expression(_),
+                    ..
+                }
+            ))
+        ));
+        assert!(matches!(
+            Object::parse_closure("() => {}; f()"),
+            Ok((
+                "; f()",
+                ObjectKind::Closure {
+                    body: ArrowBody::Block(_),
+                    ..
+                }
+            ))
+        ));
+        assert!(Object::parse_closure("() => return").is_err());
     }
 
     #[test]
     fn parse_empty_closure() {
-        assert!(Object::parse_closure("() => return").is_ok());
+        assert!(Object::parse_closure("() => {}").is_ok());
     }
 }
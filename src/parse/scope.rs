@@ -1,37 +1,121 @@
 use crate::{
     parse::*,
-    parse::{expression::Expr, identifier::Identifier, instruction::FunctionBody},
+    parse::{
+        comment::Comment,
+        error::{CollectErrors, SyntaxError},
+        expression::Expr,
+        identifier::Identifier,
+        instruction::FunctionBody,
+        span::{LineIndex, Locate, Span},
+    },
 };
 use nom::IResult;
 
+/// Keyword a variable was declared with
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeclarationKind {
+    /// Block scoped
+    Let,
+    /// Block scoped, can not be reassigned
+    Const,
+    /// Function scoped, hoisted to the start of the function
+    Var,
+}
+
+impl DeclarationKind {
+    fn parse(i: &str) -> IResult<&str, DeclarationKind> {
+        use nom::{branch::alt, combinator::map};
+        alt((
+            map(keyword("let"), |_| DeclarationKind::Let),
+            map(keyword("const"), |_| DeclarationKind::Const),
+            map(keyword("var"), |_| DeclarationKind::Var),
+        ))(i)
+    }
+}
+
+/// Single variable of a declaration,
+/// e.g. `b = 2` in `let a = 1, b = 2`
 #[derive(Debug)]
 pub struct Variable {
+    pub kind: DeclarationKind,
     pub identifier: Identifier,
+    /// Variables without value hold `undefined`
     pub assign: Option<Box<Expr>>,
+    pub span: Span,
 }
 
 impl Variable {
-    pub fn parse(i: &str) -> IResult<&str, Variable> {
-        let (i, _) = tag_ws("let")(i)?;
+    /// Recognize a declaration of one or more variables, like
+    /// ```js
+    /// let a = 1, b
+    /// ```
+    pub fn parse(i: &str) -> IResult<&str, Vec<Variable>> {
+        let (mut i, kind) = DeclarationKind::parse(i)?;
+        let mut variables = Vec::new();
+
+        loop {
+            let (rest, (variable, span)) = spanned(|i| Variable::parse_unspanned(i, kind))(i)?;
+            variables.push(Variable { span, ..variable });
+            i = rest;
+
+            match error::quiet(char_ws(','))(i) {
+                Ok((rest, _)) => i = rest,
+                Err(_) => return Ok((i, variables)),
+            }
+        }
+    }
+
+    /// Recognize a declaration of a single variable without value,
+    /// like `const x` in `for (const x of list)`
+    pub fn parse_binding(i: &str) -> IResult<&str, Variable> {
+        let (i, ((kind, identifier), span)) = spanned(nom::sequence::pair(
+            DeclarationKind::parse,
+            Identifier::parse_ws,
+        ))(i)?;
+        Ok((
+            i,
+            Variable {
+                kind,
+                identifier,
+                assign: None,
+                span,
+            },
+        ))
+    }
+
+    fn parse_unspanned(i: &str, kind: DeclarationKind) -> IResult<&str, Variable> {
         let (i, identifier) = Identifier::parse_ws(i)?;
 
         use nom::sequence::preceded;
-        match preceded(tag_ws("="), ignore_ws(Expr::parse))(i) {
-            Ok((rest, expr)) => Ok((
-                rest,
-                Variable {
-                    identifier,
-                    assign: Some(Box::new(expr)),
-                },
-            )),
-            _ => Ok((
-                i,
-                Variable {
-                    identifier,
-                    assign: None,
-                },
-            )),
-        }
+        let (i, assign) = match preceded(tag_ws("="), ignore_ws(Expr::parse))(i) {
+            Ok((rest, expr)) => (rest, Some(Box::new(expr))),
+            // Constants can not be assigned later on
+            Err(e) if kind == DeclarationKind::Const => return Err(e),
+            Err(_) => (i, None),
+        };
+
+        Ok((
+            i,
+            Variable {
+                kind,
+                identifier,
+                assign,
+                span: Span::default(),
+            },
+        ))
+    }
+}
+
+impl Locate for Variable {
+    fn locate(&mut self, lines: &LineIndex) {
+        self.span.locate(lines);
+        self.assign.locate(lines);
+    }
+}
+
+impl CollectErrors for Variable {
+    fn collect_errors(&self, errors: &mut Vec<SyntaxError>) {
+        self.assign.collect_errors(errors);
     }
 }
 
@@ -49,6 +133,29 @@ mod variable_test {
         let input = "let xyz = 1 + 1 ";
         assert!(Variable::parse(input).is_ok());
     }
+
+    #[test]
+    fn multiple() {
+        use super::DeclarationKind;
+
+        let (rest, variables) = Variable::parse("const a = 1, b = a, c = 3").unwrap();
+        assert_eq!("", rest);
+        assert_eq!(3, variables.len());
+        assert!(variables.iter().all(|v| v.kind == DeclarationKind::Const));
+        assert_eq!(
+            (12, 7),
+            (variables[1].span.start.offset, variables[1].span.end.offset)
+        );
+
+        let (_, variables) = Variable::parse("var a, b = 2").unwrap();
+        assert!(variables[0].assign.is_none());
+        assert_eq!(DeclarationKind::Var, variables[1].kind);
+    }
+
+    #[test]
+    fn const_without_value() {
+        assert!(Variable::parse("const x").is_err());
+    }
 }
 
 #[derive(Debug)]
@@ -56,14 +163,22 @@ pub struct Function {
     pub identifier: Identifier,
     pub arguments: Vec<Identifier>,
     pub body: FunctionBody,
+    /// Comments right before the function, only kept on request
+    pub comments: Vec<Comment>,
+    pub span: Span,
 }
 
 impl Function {
     pub fn parse(input: &str) -> IResult<&str, Function> {
+        let (rest, (function, span)) = spanned(Function::parse_unspanned)(input)?;
+        Ok((rest, Function { span, ..function }))
+    }
+
+    fn parse_unspanned(input: &str) -> IResult<&str, Function> {
         use nom::sequence::{delimited, pair, preceded};
 
         let (input, (identifier, arguments)) = pair(
-            preceded(tag_ws("function"), Identifier::parse_ws),
+            preceded(keyword("function"), Identifier::parse_ws),
             delimited(
                 char_ws('('),
                 concat(char_ws(','), Identifier::parse_ws),
@@ -79,11 +194,27 @@ impl Function {
                 identifier,
                 arguments,
                 body,
+                comments: Vec::new(),
+                span: Span::default(),
             },
         ))
     }
 }
 
+impl Locate for Function {
+    fn locate(&mut self, lines: &LineIndex) {
+        self.span.locate(lines);
+        self.body.locate(lines);
+        self.comments.locate(lines);
+    }
+}
+
+impl CollectErrors for Function {
+    fn collect_errors(&self, errors: &mut Vec<SyntaxError>) {
+        self.body.collect_errors(errors);
+    }
+}
+
 #[cfg(test)]
 mod function_test {
     use super::Function;
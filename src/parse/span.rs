@@ -0,0 +1,182 @@
+use std::fmt;
+
+/// Position in the source code.
+/// Lines and columns start at 1, columns count characters, not bytes.
+#[derive(Clone, Copy, PartialEq, Eq, Default, Debug)]
+pub struct Position {
+    pub offset: usize,
+    pub line: usize,
+    pub column: usize,
+}
+
+/// Part of the source code a node was parsed from.
+///
+/// The parsers only ever see the remaining input,
+/// so while parsing, offsets are counted from the end of the input.
+/// `parse::parse` locates all spans against the source code afterwards.
+#[derive(Clone, Copy, PartialEq, Eq, Default)]
+pub struct Span {
+    pub start: Position,
+    pub end: Position,
+}
+
+impl Span {
+    /// Span of the input consumed, when a parser got `input` and left `rest`
+    pub fn between(input: &str, rest: &str) -> Span {
+        let unlocated = |remaining| Position {
+            offset: remaining,
+            line: 0,
+            column: 0,
+        };
+
+        Span {
+            start: unlocated(input.len()),
+            end: unlocated(rest.len()),
+        }
+    }
+
+    /// Span starting with `self` and ending with `other`
+    pub fn to(self, other: Span) -> Span {
+        Span {
+            start: self.start,
+            end: other.end,
+        }
+    }
+
+    pub fn is_located(&self) -> bool {
+        self.start.line != 0
+    }
+}
+
+impl fmt::Debug for Span {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_located() {
+            write!(
+                f,
+                "{}:{}..{}:{}",
+                self.start.line, self.start.column, self.end.line, self.end.column
+            )
+        } else {
+            write!(f, "unlocated")
+        }
+    }
+}
+
+/// Start of every line in the source code
+pub struct LineIndex<'a> {
+    source: &'a str,
+    line_starts: Vec<usize>,
+}
+
+impl<'a> LineIndex<'a> {
+    pub fn new(source: &'a str) -> LineIndex<'a> {
+        let line_starts = std::iter::once(0)
+            .chain(source.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+
+        LineIndex {
+            source,
+            line_starts,
+        }
+    }
+
+    /// Position of the remaining input, which is `remaining` bytes long
+    pub fn position(&self, remaining: usize) -> Position {
+        let offset = self.source.len() - remaining;
+        let line = match self.line_starts.binary_search(&offset) {
+            Ok(line) => line,
+            Err(next) => next - 1,
+        };
+        let column = self.source[self.line_starts[line]..offset].chars().count() + 1;
+
+        Position {
+            offset,
+            line: line + 1,
+            column,
+        }
+    }
+
+    /// Text of the line, without the line break
+    pub fn line(&self, line: usize) -> &'a str {
+        let start = self.line_starts[line - 1];
+        let end = self
+            .line_starts
+            .get(line)
+            .map(|next| next - 1)
+            .unwrap_or_else(|| self.source.len());
+
+        self.source[start..end].trim_end_matches('\r')
+    }
+}
+
+/// Nodes carrying spans, which need to be located in the source code
+pub trait Locate {
+    fn locate(&mut self, lines: &LineIndex);
+}
+
+impl Locate for Span {
+    fn locate(&mut self, lines: &LineIndex) {
+        if !self.is_located() {
+            self.start = lines.position(self.start.offset);
+            self.end = lines.position(self.end.offset);
+        }
+    }
+}
+
+impl<T: Locate> Locate for Box<T> {
+    fn locate(&mut self, lines: &LineIndex) {
+        self.as_mut().locate(lines)
+    }
+}
+
+impl<T: Locate> Locate for Option<T> {
+    fn locate(&mut self, lines: &LineIndex) {
+        if let Some(node) = self {
+            node.locate(lines)
+        }
+    }
+}
+
+impl<T: Locate> Locate for Vec<T> {
+    fn locate(&mut self, lines: &LineIndex) {
+        for node in self {
+            node.locate(lines)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn position() {
+        let source = "ab\ncäd\n";
+        let lines = LineIndex::new(source);
+
+        let at = |offset: usize| lines.position(source.len() - offset);
+        assert_eq!((1, 1), (at(0).line, at(0).column));
+        assert_eq!((1, 3), (at(2).line, at(2).column));
+        assert_eq!((2, 1), (at(3).line, at(3).column));
+        assert_eq!((2, 3), (at(6).line, at(6).column));
+        assert_eq!((3, 1), (at(8).line, at(8).column));
+    }
+
+    #[test]
+    fn line() {
+        let lines = LineIndex::new("first\r\nsecond");
+        assert_eq!("first", lines.line(1));
+        assert_eq!("second", lines.line(2));
+    }
+
+    #[test]
+    fn locate() {
+        let source = "let x\n  = 1";
+        let mut span = Span::between(&source[8..], &source[11..]);
+        span.locate(&LineIndex::new(source));
+
+        assert_eq!(8, span.start.offset);
+        assert_eq!(11, span.end.offset);
+        assert_eq!("2:3..2:6", format!("{:?}", span));
+    }
+}
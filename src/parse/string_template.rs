@@ -1,27 +1,361 @@
-use crate::parse::{char_ws, expression::Expr};
-use nom::{
-    character::complete::{char, none_of},
-    combinator::map,
-    multi::many0,
-    sequence::delimited,
-    IResult,
+use crate::parse::{
+    char_ws,
+    error::{self, CollectErrors, Expected, SyntaxError},
+    expression::Expr,
+    identifier::unicode_escape,
+    ignore_ws,
+    span::{LineIndex, Locate},
+    whitespace,
 };
+use nom::{character::complete::char, IResult};
 
 /// Template for String interpolation
 #[derive(Debug)]
 pub struct StringTemplate {
-    start: String,
-    end: Vec<(Expr, String)>,
+    /// Text before the first interpolation, with all escapes decoded
+    pub start: String,
+    pub end: Vec<(Expr, String)>,
+    /// Source text of all literal parts, with escapes kept as written
+    pub raw: Vec<String>,
+    /// Whether each literal part contains an invalid escape, like `\unicode`.
+    /// Only tagged templates allow them, the decoded part is `undefined` then.
+    pub invalid: Vec<bool>,
 }
 
 impl StringTemplate {
+    /// String literal in single or double quotes, or a template literal
     pub fn parse(input: &str) -> IResult<&str, StringTemplate> {
-        map(
-            delimited(char_ws('"'), many0(none_of("\"")), char('"')),
-            |list: Vec<char>| StringTemplate {
-                start: list.into_iter().collect(),
+        let (input, _) = whitespace(input)?;
+        let quote = match input.chars().next() {
+            Some(quote @ '"') | Some(quote @ '\'') => quote,
+            _ => return StringTemplate::parse_template(input),
+        };
+
+        let (rest, (start, raw)) = literal(&input[1..], Quote::String(quote), false)?;
+        let (rest, _) = char(quote)(rest)?;
+        Ok((
+            rest,
+            StringTemplate {
+                start: start.unwrap_or_default(),
                 end: Vec::new(),
+                raw: vec![raw],
+                invalid: vec![false],
             },
-        )(input)
+        ))
+    }
+
+    /// Template literal, like
+    /// ```js
+    /// `x is ${x}`
+    /// ```
+    pub fn parse_template(input: &str) -> IResult<&str, StringTemplate> {
+        StringTemplate::template(input, false)
+    }
+
+    /// Template literal following a tag, like
+    /// ```js
+    /// tag`x is ${x}`
+    /// ```
+    /// which may contain invalid escapes.
+    pub fn parse_tagged(input: &str) -> IResult<&str, StringTemplate> {
+        StringTemplate::template(input, true)
+    }
+
+    fn template(input: &str, tagged: bool) -> IResult<&str, StringTemplate> {
+        let (input, _) = char_ws('`')(input)?;
+        let (mut input, (start, raw)) = literal(input, Quote::Template, tagged)?;
+        let mut invalid = vec![start.is_none()];
+        let mut raw = vec![raw];
+        let mut end = Vec::new();
+
+        while let Some(rest) = input.strip_prefix("${") {
+            let (rest, expr) = ignore_ws(Expr::parse_sequence)(rest)?;
+            let (rest, _) = char_ws('}')(rest)?;
+            let (rest, (text, text_raw)) = literal(rest, Quote::Template, tagged)?;
+            invalid.push(text.is_none());
+            end.push((expr, text.unwrap_or_default()));
+            raw.push(text_raw);
+            input = rest;
+        }
+
+        let (input, _) = char('`')(input)?;
+        Ok((
+            input,
+            StringTemplate {
+                start: start.unwrap_or_default(),
+                end,
+                raw,
+                invalid,
+            },
+        ))
+    }
+
+    /// All literal parts of the template, one more than there are interpolations
+    pub fn strings(&self) -> impl Iterator<Item = &String> {
+        std::iter::once(&self.start).chain(self.end.iter().map(|(_, text)| text))
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Quote {
+    /// `'` or `"`
+    String(char),
+    /// `` ` ``, ending at interpolations as well
+    Template,
+}
+
+/// Literal text up to the closing quote or the next interpolation,
+/// both decoded and as written.
+/// Line breaks in templates are normalized to `\n`.
+/// With `tagged`, invalid escapes leave the text undecoded instead of failing.
+fn literal(input: &str, quote: Quote, tagged: bool) -> IResult<&str, (Option<String>, String)> {
+    let mut text = String::new();
+    let mut valid = true;
+    let mut rest = input;
+
+    loop {
+        let mut chars = rest.chars();
+        match (chars.next(), quote) {
+            (Some(c), Quote::String(q)) if c == q => break,
+            (Some('`'), Quote::Template) => break,
+            (Some('$'), Quote::Template) if chars.as_str().starts_with('{') => break,
+            (Some('\\'), _) if tagged => {
+                match error::quiet(|input| escape(input, quote))(chars.as_str()) {
+                    Ok((after, decoded)) => {
+                        text.extend(decoded);
+                        rest = after;
+                    }
+                    // Only the raw text is left, which continues after the `\`
+                    Err(_) => {
+                        valid = false;
+                        rest = chars.as_str();
+                    }
+                }
+                continue;
+            }
+            (Some('\\'), _) => {
+                let (after, decoded) = escape(chars.as_str(), quote)?;
+                text.extend(decoded);
+                rest = after;
+                continue;
+            }
+            (Some('\r'), Quote::Template) => {
+                text.push('\n');
+                rest = chars.as_str();
+                rest = rest.strip_prefix('\n').unwrap_or(rest);
+                continue;
+            }
+            // Only escaped line breaks are allowed in strings
+            (Some('\n'), Quote::String(_)) | (Some('\r'), Quote::String(_)) | (None, _) => {
+                let expected = match quote {
+                    Quote::String(q) => Expected::Char(q),
+                    Quote::Template => Expected::Char('`'),
+                };
+                error::expected(rest, expected);
+                return Err(nom::Err::Error((rest, nom::error::ErrorKind::Char)));
+            }
+            (Some(c), _) => text.push(c),
+        }
+        rest = chars.as_str();
+    }
+
+    let raw = &input[..input.len() - rest.len()];
+    let raw = match quote {
+        Quote::Template => raw.replace("\r\n", "\n").replace('\r', "\n"),
+        Quote::String(_) => raw.to_string(),
+    };
+    Ok((rest, (Some(text).filter(|_| valid), raw)))
+}
+
+/// Escape sequence following a `\`.
+/// Line continuations don't result in a character.
+fn escape(input: &str, quote: Quote) -> IResult<&str, Option<char>> {
+    let invalid = || {
+        error::expected(input, Expected::Label("escape sequence"));
+        Err(nom::Err::Error((input, nom::error::ErrorKind::Escaped)))
+    };
+
+    let mut chars = input.chars();
+    let c = match chars.next() {
+        Some(c) => c,
+        None => return invalid(),
+    };
+    let rest = chars.as_str();
+
+    let decoded = match c {
+        'b' => '\u{8}',
+        'f' => '\u{c}',
+        'n' => '\n',
+        'r' => '\r',
+        't' => '\t',
+        'v' => '\u{b}',
+        '\r' => return Ok((rest.strip_prefix('\n').unwrap_or(rest), None)),
+        '\n' | '\u{2028}' | '\u{2029}' => return Ok((rest, None)),
+        'x' => match rest.get(..2) {
+            Some(hex) if hex.chars().all(|c| c.is_ascii_hexdigit()) => {
+                let code = u8::from_str_radix(hex, 16).unwrap();
+                return Ok((&rest[2..], Some(code as char)));
+            }
+            _ => return invalid(),
+        },
+        'u' => match unicode_escape(rest) {
+            Some((code, rest)) => return Ok(surrogates(code, rest)),
+            None => return invalid(),
+        },
+        '0' if !rest.starts_with(|c: char| c.is_ascii_digit()) => '\0',
+        // Legacy octal escapes, like `\251`, only in non-template strings
+        '0'..='9' if quote == Quote::Template => return invalid(),
+        '0'..='7' => {
+            let max = if c <= '3' { 3 } else { 2 };
+            let digits = input
+                .chars()
+                .take(max)
+                .take_while(|c| ('0'..='7').contains(c))
+                .count();
+            let code = u32::from_str_radix(&input[..digits], 8).unwrap();
+            return Ok((&input[digits..], std::char::from_u32(code)));
+        }
+        c => c,
+    };
+
+    Ok((rest, Some(decoded)))
+}
+
+/// Character of a `\u` escape,
+/// joining surrogate pairs written as two escapes, like `\uD83D\uDE00`.
+/// Lone surrogates can't be represented and are replaced by `U+FFFD`.
+fn surrogates(code: u32, rest: &str) -> (&str, Option<char>) {
+    if (0xD800..0xDC00).contains(&code) {
+        if let Some((low, after)) = rest.strip_prefix("\\u").and_then(unicode_escape) {
+            if (0xDC00..0xE000).contains(&low) {
+                let code = 0x10000 + ((code - 0xD800) << 10) + (low - 0xDC00);
+                return (after, std::char::from_u32(code));
+            }
+        }
+    }
+
+    (
+        rest,
+        Some(std::char::from_u32(code).unwrap_or(std::char::REPLACEMENT_CHARACTER)),
+    )
+}
+
+impl Locate for StringTemplate {
+    fn locate(&mut self, lines: &LineIndex) {
+        for (expr, _) in &mut self.end {
+            expr.locate(lines);
+        }
+    }
+}
+
+impl CollectErrors for StringTemplate {
+    fn collect_errors(&self, errors: &mut Vec<SyntaxError>) {
+        for (expr, _) in &self.end {
+            expr.collect_errors(errors);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::StringTemplate;
+
+    fn decoded(input: &str) -> Result<String, ()> {
+        match StringTemplate::parse(input) {
+            Ok(("", template)) => Ok(template.start),
+            _ => Err(()),
+        }
+    }
+
+    #[test]
+    fn quoted() {
+        let (rest, template) = StringTemplate::parse("\"a ${b}\" c").unwrap();
+        assert_eq!(" c", rest);
+        assert_eq!("a ${b}", template.start);
+        assert!(template.end.is_empty());
+
+        assert_eq!(Ok("it's".to_string()), decoded("\"it's\""));
+        assert_eq!(Ok("say \"hi\"".to_string()), decoded("'say \"hi\"'"));
+        assert!(decoded("'open").is_err());
+        assert!(decoded("'line\nbreak'").is_err());
+    }
+
+    #[test]
+    fn escapes() {
+        assert_eq!(Ok("say \"hi\"".to_string()), decoded("\"say \\\"hi\\\"\""));
+        assert_eq!(Ok("a\nb\tc\\".to_string()), decoded("'a\\nb\\tc\\\\'"));
+        assert_eq!(
+            Ok("\u{8}\u{c}\r\u{b}\0".to_string()),
+            decoded("'\\b\\f\\r\\v\\0'")
+        );
+        assert_eq!(Ok("A".to_string()), decoded("'\\x41'"));
+        assert_eq!(Ok("é😀".to_string()), decoded("'\\u00e9\\u{1F600}'"));
+        assert_eq!(Ok("😀".to_string()), decoded("'\\uD83D\\uDE00'"));
+        assert_eq!(Ok("\u{FFFD}x".to_string()), decoded("'\\uD83Dx'"));
+        assert_eq!(Ok("©a".to_string()), decoded("'\\251\\a'"));
+        assert_eq!(Ok("\u{1}8".to_string()), decoded("'\\18'"));
+        assert!(decoded("'\\x4'").is_err());
+        assert!(decoded("'\\u{110000}'").is_err());
+        assert!(decoded("'\\u12'").is_err());
+    }
+
+    #[test]
+    fn line_continuation() {
+        assert_eq!(Ok("onetwo".to_string()), decoded("'one\\\ntwo'"));
+        assert_eq!(Ok("onetwo".to_string()), decoded("'one\\\r\ntwo'"));
+    }
+
+    #[test]
+    fn raw() {
+        let (_, template) = StringTemplate::parse("'a\\tb'").unwrap();
+        assert_eq!("a\tb", template.start);
+        assert_eq!(vec!["a\\tb"], template.raw);
+
+        let (_, template) = StringTemplate::parse("`\\u0041${x}\r\n`").unwrap();
+        assert_eq!(vec!["\\u0041", "\n"], template.raw);
+    }
+
+    #[test]
+    fn interpolation() {
+        let (rest, template) = StringTemplate::parse("`x = ${ x + 1 }, y = ${y}!`").unwrap();
+        assert_eq!("", rest);
+        let strings: Vec<_> = template.strings().map(String::as_str).collect();
+        assert_eq!(vec!["x = ", ", y = ", "!"], strings);
+    }
+
+    #[test]
+    fn multiline() {
+        let (_, template) = StringTemplate::parse("`one\r\ntwo \\` $ {}`").unwrap();
+        assert_eq!("one\ntwo ` $ {}", template.start);
+        assert!(StringTemplate::parse("`\\1`").is_err());
+    }
+
+    #[test]
+    fn tagged_invalid_escapes() {
+        assert!(StringTemplate::parse_template("`\\unicode`").is_err());
+
+        let (rest, template) = StringTemplate::parse_tagged("`\\unicode ${x}\\x1 \\n`").unwrap();
+        assert_eq!("", rest);
+        assert_eq!(vec![true, true], template.invalid);
+        assert_eq!(vec!["\\unicode ", "\\x1 \\n"], template.raw);
+
+        let (_, template) = StringTemplate::parse_tagged("`a\\tb${x}\\1`").unwrap();
+        assert_eq!(vec![false, true], template.invalid);
+        assert_eq!("a\tb", template.start);
+        assert!(StringTemplate::parse_tagged("`\\`").is_err());
+    }
+
+    #[test]
+    fn nested() {
+        let (rest, template) = StringTemplate::parse("`a${`b${c}`}d`").unwrap();
+        assert_eq!("", rest);
+        assert_eq!(1, template.end.len());
+        assert_eq!("d", template.end[0].1);
+    }
+
+    #[test]
+    fn unterminated() {
+        assert!(StringTemplate::parse("`open ${x}").is_err());
+        assert!(StringTemplate::parse("`${x`").is_err());
     }
 }
@@ -0,0 +1,152 @@
+use crate::parse::{
+    char_ws,
+    error::{self, CollectErrors, Expected, SyntaxError},
+    expression::Expr,
+    instruction::FunctionBody,
+    keyword,
+    span::{LineIndex, Locate, Span},
+    spanned, whitespace,
+};
+use nom::{
+    branch::alt,
+    combinator::map,
+    sequence::{delimited, pair, preceded},
+    IResult,
+};
+
+/// ```js
+/// switch (value) {
+///     case 1: return "one"
+///     default: return "many"
+/// }
+/// ```
+#[derive(Debug)]
+pub struct Switch {
+    pub discriminant: Box<Expr>,
+    /// Clauses in order of appearance.
+    /// Once a clause matches, all following ones are executed as well, until `break`.
+    pub cases: Vec<Case>,
+}
+
+/// Clause of a switch statement, like `case 1: return "one"`
+#[derive(Debug)]
+pub struct Case {
+    /// `None` for the `default` clause
+    pub test: Option<Box<Expr>>,
+    pub body: FunctionBody,
+    pub span: Span,
+}
+
+impl Switch {
+    pub fn parse(input: &str) -> IResult<&str, Switch> {
+        let (input, discriminant) = preceded(
+            keyword("switch"),
+            delimited(char_ws('('), Expr::parse_sequence, char_ws(')')),
+        )(input)?;
+        let (mut input, _) = char_ws('{')(input)?;
+
+        let mut cases: Vec<Case> = Vec::new();
+        while let Ok((rest, case)) = Case::parse(input) {
+            // Only a single `default` clause is allowed
+            if case.test.is_none() && cases.iter().any(|case| case.test.is_none()) {
+                let (clause, _) = whitespace(input)?;
+                error::expected(clause, Expected::Token("case"));
+                return Err(nom::Err::Error((clause, nom::error::ErrorKind::Verify)));
+            }
+
+            cases.push(case);
+            input = rest;
+        }
+
+        let (input, _) = char_ws('}')(input)?;
+        Ok((
+            input,
+            Switch {
+                discriminant: Box::new(discriminant),
+                cases,
+            },
+        ))
+    }
+}
+
+impl Case {
+    fn parse(input: &str) -> IResult<&str, Case> {
+        let (input, ((test, body), span)) = spanned(pair(
+            alt((
+                map(preceded(keyword("case"), Expr::parse_sequence), |test| {
+                    Some(Box::new(test))
+                }),
+                map(keyword("default"), |_| None),
+            )),
+            preceded(char_ws(':'), FunctionBody::parse),
+        ))(input)?;
+
+        Ok((input, Case { test, body, span }))
+    }
+}
+
+impl Locate for Switch {
+    fn locate(&mut self, lines: &LineIndex) {
+        self.discriminant.locate(lines);
+        self.cases.locate(lines);
+    }
+}
+
+impl Locate for Case {
+    fn locate(&mut self, lines: &LineIndex) {
+        self.span.locate(lines);
+        self.test.locate(lines);
+        self.body.locate(lines);
+    }
+}
+
+impl CollectErrors for Switch {
+    fn collect_errors(&self, errors: &mut Vec<SyntaxError>) {
+        self.discriminant.collect_errors(errors);
+        self.cases.collect_errors(errors);
+    }
+}
+
+impl CollectErrors for Case {
+    fn collect_errors(&self, errors: &mut Vec<SyntaxError>) {
+        self.test.collect_errors(errors);
+        self.body.collect_errors(errors);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cases() {
+        let input = "switch (x) {
+            case 1:
+            case 2: y = 1; break
+            default:
+                y = 2
+            case 'three': {}
+        }";
+        let (rest, switch) = Switch::parse(input).unwrap();
+        assert_eq!("", rest);
+
+        let statements: Vec<_> = switch
+            .cases
+            .iter()
+            .map(|case| case.body.instructions.len())
+            .collect();
+        assert_eq!(vec![0, 2, 1, 1], statements);
+        assert!(switch.cases[2].test.is_none());
+    }
+
+    #[test]
+    fn empty() {
+        assert!(Switch::parse("switch (x) {}").is_ok());
+        assert!(Switch::parse("switch (x) { case }").is_err());
+    }
+
+    #[test]
+    fn single_default() {
+        assert!(Switch::parse("switch (x) { default: default: }").is_err());
+    }
+}
@@ -1,52 +1,24 @@
+use crate::parse::comment;
+use crate::parse::error::{expected, Expected};
+use crate::parse::identifier::is_id_continue;
+use crate::parse::span::Span;
 use nom::IResult;
+use std::cell::Cell;
+use std::thread::LocalKey;
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    #[test]
-    fn test_whitespace() {
-        assert_eq!(Ok(("hello", "")), whitespace("hello"));
-
-        assert_eq!(Ok(("hello", "\n ")), whitespace("\n hello"));
-
-        assert_eq!(Ok(("", "    ")), whitespace("    "));
-        assert_eq!(Ok(("", "")), whitespace(""));
-    }
-
-    #[test]
-    fn test_ignore_ws() {
-        use nom::bytes::complete::tag;
-        assert_eq!(Ok(("", "hello")), ignore_ws(tag("hello"))("hello"));
-        assert_eq!(Ok(("", "hello")), ignore_ws(tag("hello"))("   hello"));
-    }
-
-    #[test]
-    fn test_tag_ws() {
-        assert_eq!(Ok(("", "hello")), tag_ws("hello")("hello"));
-        assert_eq!(Ok(("", "hello")), tag_ws("hello")("   hello"));
-    }
-
-    #[test]
-    fn test_concat() {
-        let i = "Q,Q,Q,Q";
-        assert_eq!(
-            concat(char_ws(','), char_ws('Q'))(i),
-            Ok(("", vec!['Q', 'Q', 'Q', 'Q']))
-        );
-    }
-
-    #[test]
-    fn test_empty_concat() {
-        let i = "";
-        assert_eq!(concat(char_ws(','), char_ws('Q'))(i), Ok(("", vec![])));
-    }
-}
-
-/// Remove all whitespace, newlines, tabs etc.
+/// Remove all whitespace, newlines, tabs and comments.
 /// Will always suceed
 pub fn whitespace(s: &str) -> IResult<&str, &str> {
-    nom::bytes::complete::take_while(|c| c == ' ' || c == '\n' || c == '\r' || c == '\t')(s)
+    let mut rest = s;
+    loop {
+        rest = rest.trim_start_matches([' ', '\n', '\r', '\t']);
+        match comment::recognize(rest) {
+            Some((after, _, _)) => rest = after,
+            None => break,
+        }
+    }
+
+    Ok((rest, &s[..s.len() - rest.len()]))
 }
 
 /// Wrap around a Parser to automatically ignore preceding whitespace
@@ -59,9 +31,32 @@ pub fn ignore_ws<'a, T>(
     }
 }
 
+/// Wrap around a Parser to also return the Span of the recognized input.
+/// Preceding whitespace is not part of the Span.
+pub fn spanned<'a, T>(
+    f: impl Fn(&'a str) -> IResult<&'a str, T>,
+) -> impl Fn(&'a str) -> IResult<&'a str, (T, Span)> {
+    move |input: &str| {
+        let (input, _) = whitespace(input)?;
+        let (rest, result) = f(input)?;
+        Ok((rest, (result, Span::between(input, rest))))
+    }
+}
+
 /// Tags a string while ignoring preceding whitespace
-pub fn tag_ws<'a>(t: &'a str) -> impl Fn(&'a str) -> IResult<&'a str, &'a str> {
-    ignore_ws(move |input: &str| nom::bytes::complete::tag(t)(input))
+pub fn tag_ws<'a>(t: &'static str) -> impl Fn(&'a str) -> IResult<&'a str, &'a str> {
+    ignore_ws(move |input: &'a str| {
+        nom::bytes::complete::tag(t)(input).inspect_err(|_| expected(input, Expected::Token(t)))
+    })
+}
+
+/// Tags a keyword while ignoring preceding whitespace.
+/// Only whole words are recognized, `letter` does not start with `let`.
+pub fn keyword<'a>(k: &'static str) -> impl Fn(&'a str) -> IResult<&'a str, &'a str> {
+    not_followed(tag_ws(k), |input: &'a str| match input.chars().next() {
+        Some(c) if is_id_continue(c) || c == '\\' => Ok((input, c)),
+        _ => Err(nom::Err::Error((input, nom::error::ErrorKind::Alpha))),
+    })
 }
 
 pub fn not_followed<'a, A, B>(
@@ -77,7 +72,12 @@ pub fn not_followed<'a, A, B>(
 
 /// Tags a character while ignoring preceding whitespace
 pub fn char_ws(c: char) -> impl Fn(&str) -> IResult<&str, char> {
-    move |input: &str| ignore_ws(nom::character::complete::char(c))(input)
+    move |input: &str| {
+        ignore_ws(|input| {
+            nom::character::complete::char(c)(input)
+                .inspect_err(|_| expected(input, Expected::Char(c)))
+        })(input)
+    }
 }
 
 /// List of Elements, seperated by `sep` parser, might be empty
@@ -90,11 +90,9 @@ pub fn concat<'a, T, Elem>(
 ) -> impl Fn(&'a str) -> IResult<&'a str, Vec<Elem>> {
     move |input: &str| {
         let mut v: Vec<Elem> = Vec::new();
-        let first = tag_elem(input);
-        let (mut input, elem) = if first.is_ok() {
-            first.unwrap()
-        } else {
-            return Ok((input, v));
+        let (mut input, elem) = match tag_elem(input) {
+            Ok(first) => first,
+            Err(_) => return Ok((input, v)),
         };
 
         v.push(elem);
@@ -113,23 +111,89 @@ pub fn concat<'a, T, Elem>(
     }
 }
 
-/// Fold a list of Elements, tagged be `tag_elem` and seperated by `sep`
-pub fn fold_concat<'a, T, E>(
-    sep: impl Fn(&'a str) -> IResult<&'a str, T>,
-    tag_elem: impl Fn(&'a str) -> IResult<&'a str, E>,
-    folding: impl Fn(E, E) -> E,
-) -> impl Fn(&'a str) -> IResult<&'a str, E> {
-    move |input: &str| {
-        let (rest, list) = concat(&sep, &tag_elem)(input)?;
-        if list.len() == 0 {
-            return Err(nom::Err::Error((
-                rest,
-                nom::error::ErrorKind::SeparatedList,
-            )));
-        }
+/// Thread local flag, which is set for as long as the guard lives.
+/// Dropping the guard restores the previous value, even when unwinding from a panic.
+pub struct FlagGuard {
+    flag: &'static LocalKey<Cell<bool>>,
+    previous: bool,
+}
+
+impl FlagGuard {
+    pub fn set(flag: &'static LocalKey<Cell<bool>>, value: bool) -> FlagGuard {
+        let previous = flag.with(|flag| flag.replace(value));
+        FlagGuard { flag, previous }
+    }
+}
+
+impl Drop for FlagGuard {
+    fn drop(&mut self) {
+        let previous = self.previous;
+        self.flag.with(|flag| flag.set(previous));
+    }
+}
 
-        let mut iter = list.into_iter();
-        let fst = iter.next().unwrap();
-        Ok((rest, iter.fold(fst, &folding)))
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_whitespace() {
+        assert_eq!(Ok(("hello", "")), whitespace("hello"));
+
+        assert_eq!(Ok(("hello", "\n ")), whitespace("\n hello"));
+
+        assert_eq!(Ok(("", "    ")), whitespace("    "));
+        assert_eq!(Ok(("", "")), whitespace(""));
+    }
+
+    #[test]
+    fn test_comments() {
+        assert_eq!(Ok(("x", "// a\n/* b */ ")), whitespace("// a\n/* b */ x"));
+        assert_eq!(Ok(("", "// a")), whitespace("// a"));
+        assert_eq!(Ok(("/* a", " ")), whitespace(" /* a"));
+        assert_eq!(Ok(("/ 2", "")), whitespace("/ 2"));
+    }
+
+    #[test]
+    fn test_ignore_ws() {
+        use nom::bytes::complete::tag;
+        assert_eq!(Ok(("", "hello")), ignore_ws(tag("hello"))("hello"));
+        assert_eq!(Ok(("", "hello")), ignore_ws(tag("hello"))("   hello"));
+    }
+
+    #[test]
+    fn test_tag_ws() {
+        assert_eq!(Ok(("", "hello")), tag_ws("hello")("hello"));
+        assert_eq!(Ok(("", "hello")), tag_ws("hello")("   hello"));
+    }
+
+    #[test]
+    fn test_keyword() {
+        assert_eq!(Ok((" x", "let")), keyword("let")(" let x"));
+        assert_eq!(Ok(("(x)", "if")), keyword("if")("if(x)"));
+        assert!(keyword("let")("letter").is_err());
+        assert!(keyword("if")("if_").is_err());
+    }
+
+    #[test]
+    fn test_spanned() {
+        let (rest, (result, span)) = spanned(tag_ws("hello"))("  hello world").unwrap();
+        assert_eq!((" world", "hello"), (rest, result));
+        assert_eq!((11, 6), (span.start.offset, span.end.offset));
+    }
+
+    #[test]
+    fn test_concat() {
+        let i = "Q,Q,Q,Q";
+        assert_eq!(
+            concat(char_ws(','), char_ws('Q'))(i),
+            Ok(("", vec!['Q', 'Q', 'Q', 'Q']))
+        );
+    }
+
+    #[test]
+    fn test_empty_concat() {
+        let i = "";
+        assert_eq!(concat(char_ws(','), char_ws('Q'))(i), Ok(("", vec![])));
     }
 }
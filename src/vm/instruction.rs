@@ -5,27 +5,107 @@ pub type StackAddress = usize;
 /// Address in Function Stack
 pub type InstructionAddress = usize;
 
+#[derive(Debug, Clone, PartialEq)]
 pub enum Instruction {
     StoreGlobal(StackAddress),
     LoadGlobal(StackAddress),
     Store(StackAddress), // Store relative to SP
     Load(StackAddress),  // Load relative to SP
+    /// Store relative to SP, starting a fresh binding,
+    /// which is no longer shared with previously created closures
+    Bind(StackAddress),
+    /// Load from the enviroment of the running closure
+    LoadCaptured(usize),
+    /// Store into the enviroment of the running closure
+    StoreCaptured(usize),
     Push(Object),
-    Get,                            // first.second or a['b'] or a[12]
-    Jump,                           // Calling Closures
-    JumpStatic(InstructionAddress), //
-    JumpConditional(InstructionAddress),
+    Pop,
+    Dup,
+    Dup2,
+    Get,                                 // first.second or a['b'] or a[12]
+    Set,                                 // first.second = third
+    Delete,                              // delete first.second, pushing whether it's gone
+    Jump(usize),                         // Calling Closures, with the number of arguments
+    JumpStatic(InstructionAddress),      //
+    JumpConditional(InstructionAddress), // Jump if the popped value is falsy
+    /// Pop a value and jump to the first case strictly equal to it,
+    /// or to `default` if there is none
+    JumpTable {
+        cases: Vec<(Object, InstructionAddress)>,
+        default: InstructionAddress,
+    },
+    /// Install an exception handler,
+    /// which continues at the address with the exception pushed
+    Try(InstructionAddress),
+    /// Remove the innermost exception handler
+    EndTry,
+    /// Pop a value and raise it as exception
+    Throw,
+    /// Replace the value on top with an iterator over its elements,
+    /// for `for ... of`
+    Iterate,
+    /// Replace the value on top with an iterator over its property names,
+    /// for `for ... in`
+    IterateKeys,
+    /// Pop an iterator and push its next value,
+    /// or jump if it is exhausted
+    Next(InstructionAddress),
+    /// Entry of every function,
+    /// reserves the space for arguments and local variables
+    Enter {
+        arguments: usize,
+        locals: usize,
+    },
+    Return,
+    Closure {
+        function: InstructionAddress,
+        captures: Vec<Capture>,
+    },
+    MakeArray(usize),
+    MakeObject(usize),
     Add,
     Subtract,
     Mod,
     Div,
     Mul,
+    Exponent,
     And,
     Or,
+    BitAnd,
+    BitOr,
+    BitXor,
+    LeftShift,
+    RightShift,
+    UnsignedRightShift,
     Equal,
     NotEqual,
+    StrictEqual,
+    StrictNotEqual,
     SmallerEqual,
     GreaterEqual,
+    Smaller,
+    Greater,
+    InstanceOf,
+    In,
     Not,
     Negation,
+    /// Unary `+`
+    ToNumber,
+    /// Like `ToNumber`, but BigInts stay as they are
+    ToNumeric,
+    /// Add one to the number or BigInt on top
+    Increment,
+    /// Subtract one from the number or BigInt on top
+    Decrement,
+    BitNot,
+    TypeOf,
+}
+
+/// Variable captured by a closure at the time of its creation
+#[derive(Debug, Clone, PartialEq)]
+pub enum Capture {
+    /// Local variable of the function creating the closure
+    Local(StackAddress),
+    /// Variable the function creating the closure captured itself
+    Captured(usize),
 }
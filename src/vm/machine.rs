@@ -1,34 +1,1619 @@
 use crate::vm::{
-    instruction::{InstructionAddress, StackAddress},
-    Instruction, Object,
+    instruction::{Capture, InstructionAddress, StackAddress},
+    object::Gc,
+    Instruction, Iteration, Object,
 };
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fmt;
+use std::rc::Rc;
+
+/// Errors aborting the execution of a script
+#[derive(Debug, Clone, PartialEq)]
+pub enum RuntimeError {
+    TypeError(String),
+    RangeError(String),
+    /// Value thrown by the script, which no `catch` block handled
+    Exception(Object),
+}
+
+impl RuntimeError {
+    /// Value seen by `catch` blocks
+    pub fn to_object(&self) -> Object {
+        let (name, message) = match self {
+            RuntimeError::TypeError(message) => ("TypeError", message),
+            RuntimeError::RangeError(message) => ("RangeError", message),
+            RuntimeError::Exception(value) => return value.clone(),
+        };
+
+        let mut error = HashMap::new();
+        error.insert(
+            Rc::new("name".to_string()),
+            Object::String(Rc::new(name.to_string())),
+        );
+        error.insert(
+            Rc::new("message".to_string()),
+            Object::String(Rc::new(message.clone())),
+        );
+        Object::Map(Gc::new(error))
+    }
+}
+
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RuntimeError::TypeError(message) => write!(f, "TypeError: {}", message),
+            RuntimeError::RangeError(message) => write!(f, "RangeError: {}", message),
+            RuntimeError::Exception(value) => write!(f, "Uncaught {}", value.to_string()),
+        }
+    }
+}
+
+/// Stack Frame of a running function
+struct Frame {
+    /// Instruction to continue with, once the function returns
+    return_address: InstructionAddress,
+    /// Start of the arguments and local variables on the stack
+    base: StackAddress,
+    enviroment: Rc<Vec<Gc<Object>>>,
+}
+
+/// Exception handler installed by a `try` block
+struct Handler {
+    /// Address of the `catch` block
+    catch: InstructionAddress,
+    /// Number of calling frames, when the handler was installed
+    depth: usize,
+    /// Height of the stack, when the handler was installed
+    stack: usize,
+}
 
 /// Virtual Stack Machine to interpret Instructions
 pub struct VirtualMachine {
     stack: Vec<Object>,
     instructions: Vec<Instruction>,
-    currentFp: InstructionAddress,
-    // currentSp: StackAddress,
-    functionPointerStack: Vec<InstructionAddress>,
-    // stackPointerStack: Vec<StackAddress>,
+    /// Address of the next instruction
+    instruction_pointer: InstructionAddress,
+    frame: Frame,
+    /// Frames of all calling functions
+    frame_stack: Vec<Frame>,
+    /// Exception handlers, innermost last
+    handlers: Vec<Handler>,
 }
 
 const INITIAL_STACK_SIZE: usize = 256;
+const MAX_CALL_DEPTH: usize = 10_000;
+
 impl VirtualMachine {
     pub fn new(instructions: Vec<Instruction>) -> VirtualMachine {
         VirtualMachine {
             stack: Vec::with_capacity(INITIAL_STACK_SIZE),
             instructions,
-            currentFp: 0,
-            // currentSp: 0,
-            functionPointerStack: Vec::new(),
-            // stackPointerStack: Vec::new(),
+            instruction_pointer: 0,
+            frame: Frame {
+                return_address: 0,
+                base: 0,
+                enviroment: Rc::new(Vec::new()),
+            },
+            frame_stack: Vec::new(),
+            handlers: Vec::new(),
+        }
+    }
+
+    /// Execute instructions, until the toplevel code returns
+    pub fn run(&mut self) -> Result<Object, RuntimeError> {
+        loop {
+            if let Some(result) = self.step()? {
+                return Ok(result);
+            }
+        }
+    }
+
+    /// Execute a single instruction.
+    /// Once the toplevel code returns, the returned value is handed out.
+    pub fn step(&mut self) -> Result<Option<Object>, RuntimeError> {
+        let instruction = match self.instructions.get(self.instruction_pointer) {
+            Some(instruction) => instruction.clone(),
+            None => return Ok(Some(Object::Undefined)),
+        };
+        self.instruction_pointer += 1;
+
+        match self.execute(instruction) {
+            Err(error) => self.unwind(error).map(|_| None),
+            result => result,
+        }
+    }
+
+    /// Continue with the innermost exception handler.
+    /// Without any handler left, the error aborts the script.
+    fn unwind(&mut self, error: RuntimeError) -> Result<(), RuntimeError> {
+        let handler = match self.handlers.pop() {
+            Some(handler) => handler,
+            None => return Err(error),
+        };
+
+        // Leave all functions called within the `try` block
+        if self.frame_stack.len() > handler.depth {
+            self.frame_stack.truncate(handler.depth + 1);
+            self.frame = self.frame_stack.pop().expect("frame of the handler");
+        }
+        self.stack.truncate(handler.stack);
+        self.stack.push(error.to_object());
+        self.instruction_pointer = handler.catch;
+
+        Ok(())
+    }
+
+    fn execute(&mut self, instruction: Instruction) -> Result<Option<Object>, RuntimeError> {
+        use Instruction::*;
+        match instruction {
+            StoreGlobal(address) => {
+                let value = self.pop();
+                self.store(address, value);
+            }
+            LoadGlobal(address) => self.load(address),
+            Store(address) => {
+                let value = self.pop();
+                self.store(self.frame.base + address, value);
+            }
+            Load(address) => self.load(self.frame.base + address),
+            Bind(address) => {
+                let value = self.pop();
+                self.stack[self.frame.base + address] = value;
+            }
+            LoadCaptured(index) => {
+                let value = self.frame.enviroment[index].borrow().clone();
+                self.stack.push(value);
+            }
+            StoreCaptured(index) => {
+                let value = self.pop();
+                *self.frame.enviroment[index].borrow_mut() = value;
+            }
+            Push(object) => self.stack.push(object),
+            Pop => {
+                self.pop();
+            }
+            Dup => {
+                let value = self.peek(0).clone();
+                self.stack.push(value);
+            }
+            Dup2 => {
+                let (first, second) = (self.peek(1).clone(), self.peek(0).clone());
+                self.stack.push(first);
+                self.stack.push(second);
+            }
+            Get => {
+                let key = self.pop();
+                let object = self.pop();
+                let value = get_property(&object, &key)?;
+                self.stack.push(value);
+            }
+            Set => {
+                let value = self.pop();
+                let key = self.pop();
+                let object = self.pop();
+                set_property(&object, &key, value.clone())?;
+                self.stack.push(value);
+            }
+            Delete => {
+                let key = self.pop();
+                let object = self.pop();
+                let deleted = delete_property(&object, &key)?;
+                self.stack.push(Object::Boolean(deleted));
+            }
+            Jump(arguments) => self.call(arguments)?,
+            JumpStatic(address) => self.instruction_pointer = address,
+            JumpConditional(address) => {
+                if !self.pop().truthy() {
+                    self.instruction_pointer = address;
+                }
+            }
+            JumpTable { cases, default } => {
+                let value = self.pop();
+                self.instruction_pointer = cases
+                    .iter()
+                    .find(|(case, _)| case.strict_equals(&value))
+                    .map_or(default, |(_, address)| *address);
+            }
+            Try(catch) => self.handlers.push(Handler {
+                catch,
+                depth: self.frame_stack.len(),
+                stack: self.stack.len(),
+            }),
+            EndTry => {
+                self.handlers.pop();
+            }
+            Throw => return Err(RuntimeError::Exception(self.pop())),
+            Iterate => {
+                let iteration = iterate(self.pop())?;
+                self.stack.push(Object::Iterator(Gc::new(iteration)));
+            }
+            IterateKeys => {
+                let keys = keys(&self.pop());
+                let iteration = Iteration::Values(keys.into_iter());
+                self.stack.push(Object::Iterator(Gc::new(iteration)));
+            }
+            Next(address) => {
+                let next = match self.pop() {
+                    Object::Iterator(iteration) => iteration.borrow_mut().next(),
+                    _ => unreachable!("only iterators are advanced"),
+                };
+                match next {
+                    Some(value) => self.stack.push(value),
+                    None => self.instruction_pointer = address,
+                }
+            }
+            Enter { arguments, locals } => {
+                // Surplus arguments are dropped, missing ones are undefined
+                let base = self.frame.base;
+                self.stack.resize(base + arguments, Object::Undefined);
+                self.stack
+                    .resize(base + arguments + locals, Object::Undefined);
+            }
+            Return => {
+                let value = self.pop();
+                let caller = match self.frame_stack.pop() {
+                    Some(caller) => caller,
+                    None => return Ok(Some(value)),
+                };
+
+                let frame = std::mem::replace(&mut self.frame, caller);
+                // Remove the closure called as well
+                self.stack.truncate(frame.base - 1);
+                self.stack.push(value);
+                self.instruction_pointer = frame.return_address;
+            }
+            Closure { function, captures } => {
+                let enviroment = captures
+                    .iter()
+                    .map(|capture| match capture {
+                        Capture::Local(address) => self.share(self.frame.base + address),
+                        Capture::Captured(index) => self.frame.enviroment[*index].clone(),
+                    })
+                    .collect();
+
+                self.stack.push(Object::Closure {
+                    enviroment: Rc::new(enviroment),
+                    function,
+                });
+            }
+            MakeArray(length) => {
+                let elements = self.stack.split_off(self.stack.len() - length);
+                self.stack.push(Object::Array(Gc::new(elements)));
+            }
+            MakeObject(length) => {
+                let mut pairs = self
+                    .stack
+                    .split_off(self.stack.len() - 2 * length)
+                    .into_iter();
+                let mut map = HashMap::new();
+                while let (Some(key), Some(value)) = (pairs.next(), pairs.next()) {
+                    map.insert(key.to_string(), value);
+                }
+                self.stack.push(Object::Map(Gc::new(map)));
+            }
+            Add => {
+                // Strings and objects are concatenated, even with BigInts
+                let concatenation = [self.peek(0), self.peek(1)].iter().any(|operand| {
+                    matches!(
+                        operand,
+                        Object::String(_) | Object::Array(_) | Object::Map(_)
+                    )
+                });
+                if concatenation {
+                    self.binary(|a, b| a + b);
+                } else {
+                    self.arithmetic(|a, b| a + b, |a, b| bigint(a.checked_add(b)))?;
+                }
+            }
+            Subtract => self.arithmetic(|a, b| a - b, |a, b| bigint(a.checked_sub(b)))?,
+            Mod => self.arithmetic(|a, b| a % b, |a, b| bigint(a.checked_rem(nonzero(b)?)))?,
+            Div => self.arithmetic(|a, b| a / b, |a, b| bigint(a.checked_div(nonzero(b)?)))?,
+            Mul => self.arithmetic(|a, b| a * b, |a, b| bigint(a.checked_mul(b)))?,
+            Exponent => self.arithmetic(power, bigint_pow)?,
+            And => self.binary(|a, b| if a.truthy() { b } else { a }),
+            Or => self.binary(|a, b| if a.truthy() { a } else { b }),
+            BitAnd => self.bitwise(|a, b| a & b, |a, b| Ok(a & b))?,
+            BitOr => self.bitwise(|a, b| a | b, |a, b| Ok(a | b))?,
+            BitXor => self.bitwise(|a, b| a ^ b, |a, b| Ok(a ^ b))?,
+            // Shift counts of numbers are taken modulo 32
+            LeftShift => self.bitwise(|a, b| a.wrapping_shl(b as u32), bigint_shift)?,
+            RightShift => self.bitwise(
+                |a, b| a.wrapping_shr(b as u32),
+                |a, b| bigint_shift(a, bigint(b.checked_neg())?),
+            )?,
+            UnsignedRightShift => self.numeric(
+                |a, b| a.to_uint32().wrapping_shr(b.to_uint32()) as f64,
+                |_, _| {
+                    Err(RuntimeError::TypeError(
+                        "BigInts have no unsigned right shift, use >> instead".to_string(),
+                    ))
+                },
+            )?,
+            Equal => self.binary(|a, b| Object::Boolean(a.equals(&b))),
+            NotEqual => self.binary(|a, b| Object::Boolean(!a.equals(&b))),
+            StrictEqual => self.binary(|a, b| Object::Boolean(a.strict_equals(&b))),
+            StrictNotEqual => self.binary(|a, b| Object::Boolean(!a.strict_equals(&b))),
+            SmallerEqual => self.comparison(|o| o.is_le()),
+            GreaterEqual => self.comparison(|o| o.is_ge()),
+            Smaller => self.comparison(|o| o.is_lt()),
+            Greater => self.comparison(|o| o.is_gt()),
+            InstanceOf => {
+                let constructor = self.pop();
+                let value = self.pop();
+                let result = instance_of(&value, &constructor)?;
+                self.stack.push(Object::Boolean(result));
+            }
+            In => {
+                let object = self.pop();
+                let key = self.pop();
+                let result = has_property(&object, &key)?;
+                self.stack.push(Object::Boolean(result));
+            }
+            Not => {
+                let value = self.pop();
+                self.stack.push(Object::Boolean(!value.truthy()));
+            }
+            Negation => {
+                let value = match self.pop() {
+                    Object::BigInt(n) => Object::BigInt(bigint(n.checked_neg())?),
+                    value => Object::Number(-value.to_number()),
+                };
+                self.stack.push(value);
+            }
+            ToNumber => {
+                let value = match self.pop() {
+                    Object::BigInt(_) => {
+                        return Err(RuntimeError::TypeError(
+                            "Cannot convert a BigInt value to a number".to_string(),
+                        ))
+                    }
+                    value => Object::Number(value.to_number()),
+                };
+                self.stack.push(value);
+            }
+            ToNumeric => {
+                let value = match self.pop() {
+                    value @ Object::BigInt(_) => value,
+                    value => Object::Number(value.to_number()),
+                };
+                self.stack.push(value);
+            }
+            Increment => {
+                let value = match self.pop() {
+                    Object::BigInt(n) => Object::BigInt(bigint(n.checked_add(1))?),
+                    value => Object::Number(value.to_number() + 1.0),
+                };
+                self.stack.push(value);
+            }
+            Decrement => {
+                let value = match self.pop() {
+                    Object::BigInt(n) => Object::BigInt(bigint(n.checked_sub(1))?),
+                    value => Object::Number(value.to_number() - 1.0),
+                };
+                self.stack.push(value);
+            }
+            BitNot => {
+                let value = match self.pop() {
+                    Object::BigInt(n) => Object::BigInt(!n),
+                    value => Object::Number(!value.to_int32() as f64),
+                };
+                self.stack.push(value);
+            }
+            TypeOf => {
+                let value = self.pop();
+                self.stack
+                    .push(Object::String(Rc::new(value.type_of().to_string())));
+            }
+        }
+
+        Ok(None)
+    }
+
+    fn pop(&mut self) -> Object {
+        self.stack.pop().expect("stack underflow")
+    }
+
+    /// Value `depth` slots below the top of the stack
+    fn peek(&self, depth: usize) -> &Object {
+        &self.stack[self.stack.len() - 1 - depth]
+    }
+
+    fn load(&mut self, address: StackAddress) {
+        let value = match &self.stack[address] {
+            Object::Reference(value) => value.borrow().clone(),
+            value => value.clone(),
+        };
+        self.stack.push(value);
+    }
+
+    fn store(&mut self, address: StackAddress, value: Object) {
+        match &self.stack[address] {
+            Object::Reference(shared) => *shared.borrow_mut() = value,
+            _ => self.stack[address] = value,
+        }
+    }
+
+    /// Share the variable at `address` with a closure
+    fn share(&mut self, address: StackAddress) -> Gc<Object> {
+        match &self.stack[address] {
+            Object::Reference(shared) => shared.clone(),
+            value => {
+                let shared = Gc::new(value.clone());
+                self.stack[address] = Object::Reference(shared.clone());
+                shared
+            }
+        }
+    }
+
+    /// Call the closure below the topmost `arguments` values
+    fn call(&mut self, arguments: usize) -> Result<(), RuntimeError> {
+        let base = self.stack.len() - arguments;
+        let (enviroment, function) = match &self.stack[base - 1] {
+            Object::Closure {
+                enviroment,
+                function,
+            } => (enviroment.clone(), *function),
+            other => {
+                return Err(RuntimeError::TypeError(format!(
+                    "{} is not a function",
+                    other.to_string()
+                )))
+            }
+        };
+
+        if self.frame_stack.len() >= MAX_CALL_DEPTH {
+            return Err(RuntimeError::RangeError(
+                "Maximum call stack size exceeded".to_string(),
+            ));
+        }
+
+        let caller = std::mem::replace(
+            &mut self.frame,
+            Frame {
+                return_address: self.instruction_pointer,
+                base,
+                enviroment,
+            },
+        );
+        self.frame_stack.push(caller);
+        self.instruction_pointer = function;
+
+        Ok(())
+    }
+
+    fn binary(&mut self, operation: impl Fn(Object, Object) -> Object) {
+        let right = self.pop();
+        let left = self.pop();
+        self.stack.push(operation(left, right));
+    }
+
+    /// Operation on two numbers or two BigInts, which can't be mixed
+    fn numeric(
+        &mut self,
+        number: impl Fn(&Object, &Object) -> f64,
+        bigint: impl Fn(i128, i128) -> Result<i128, RuntimeError>,
+    ) -> Result<(), RuntimeError> {
+        let right = self.pop();
+        let left = self.pop();
+        let result = match (&left, &right) {
+            (Object::BigInt(a), Object::BigInt(b)) => Object::BigInt(bigint(*a, *b)?),
+            (Object::BigInt(_), _) | (_, Object::BigInt(_)) => {
+                return Err(RuntimeError::TypeError(
+                    "Cannot mix BigInt and other types, use explicit conversions".to_string(),
+                ))
+            }
+            (a, b) => Object::Number(number(a, b)),
+        };
+        self.stack.push(result);
+        Ok(())
+    }
+
+    fn arithmetic(
+        &mut self,
+        number: impl Fn(f64, f64) -> f64,
+        bigint: impl Fn(i128, i128) -> Result<i128, RuntimeError>,
+    ) -> Result<(), RuntimeError> {
+        self.numeric(|a, b| number(a.to_number(), b.to_number()), bigint)
+    }
+
+    /// Operation on both operands converted to 32 bit integers, or on two BigInts
+    fn bitwise(
+        &mut self,
+        number: impl Fn(i32, i32) -> i32,
+        bigint: impl Fn(i128, i128) -> Result<i128, RuntimeError>,
+    ) -> Result<(), RuntimeError> {
+        self.numeric(|a, b| number(a.to_int32(), b.to_int32()) as f64, bigint)
+    }
+
+    fn comparison(&mut self, accept: impl Fn(std::cmp::Ordering) -> bool) {
+        self.binary(|a, b| Object::Boolean(a.compare(&b).is_some_and(&accept)));
+    }
+}
+
+/// `**` on numbers. Unlike `powf`, a NaN exponent and ±1 to an infinite power are NaN.
+fn power(base: f64, exponent: f64) -> f64 {
+    if exponent.is_nan() || (base.abs() == 1.0 && exponent.is_infinite()) {
+        return f64::NAN;
+    }
+    base.powf(exponent)
+}
+
+/// Result of BigInt arithmetic, which is limited to 128 bits
+fn bigint(result: Option<i128>) -> Result<i128, RuntimeError> {
+    result.ok_or_else(|| RuntimeError::RangeError("Maximum BigInt size exceeded".to_string()))
+}
+
+/// Divisor of a BigInt division
+fn nonzero(divisor: i128) -> Result<i128, RuntimeError> {
+    match divisor {
+        0 => Err(RuntimeError::RangeError("Division by zero".to_string())),
+        divisor => Ok(divisor),
+    }
+}
+
+fn bigint_pow(base: i128, exponent: i128) -> Result<i128, RuntimeError> {
+    if exponent < 0 {
+        return Err(RuntimeError::RangeError(
+            "Exponent must be non-negative".to_string(),
+        ));
+    }
+    bigint(
+        u32::try_from(exponent)
+            .ok()
+            .and_then(|e| base.checked_pow(e)),
+    )
+}
+
+/// Shift to the left, or to the right for negative `shift`
+fn bigint_shift(value: i128, shift: i128) -> Result<i128, RuntimeError> {
+    if shift < 0 {
+        // Shifting right rounds towards negative infinity
+        return Ok(value >> shift.unsigned_abs().min(127));
+    }
+    match u32::try_from(shift) {
+        _ if value == 0 => Ok(0),
+        Ok(shift) if shift < 128 && (value << shift) >> shift == value => Ok(value << shift),
+        _ => bigint(None),
+    }
+}
+
+/// Numbers and numeric strings are used to index arrays and strings
+fn index(key: &Object) -> Option<usize> {
+    match key {
+        Object::Number(n) if *n >= 0.0 && n.fract() == 0.0 => Some(*n as usize),
+        Object::String(s) => s.parse().ok(),
+        _ => None,
+    }
+}
+
+fn get_property(object: &Object, key: &Object) -> Result<Object, RuntimeError> {
+    Ok(match object {
+        Object::Array(list) => match index(key) {
+            Some(i) => list.borrow().get(i).cloned().unwrap_or(Object::Undefined),
+            None if *key.to_string() == "length" => Object::Number(list.borrow().len() as f64),
+            None => Object::Undefined,
+        },
+        Object::String(s) => match index(key) {
+            Some(i) => s
+                .chars()
+                .nth(i)
+                .map(|c| Object::String(Rc::new(c.to_string())))
+                .unwrap_or(Object::Undefined),
+            None if *key.to_string() == "length" => Object::Number(s.chars().count() as f64),
+            None => Object::Undefined,
+        },
+        Object::Map(map) => map
+            .borrow()
+            .get(&key.to_string())
+            .cloned()
+            .unwrap_or(Object::Undefined),
+        Object::Undefined | Object::Null => {
+            return Err(RuntimeError::TypeError(format!(
+                "Cannot read property '{}' of {}",
+                key.to_string(),
+                object.to_string()
+            )))
+        }
+        _ => Object::Undefined,
+    })
+}
+
+/// Check for a property like `key in object`
+fn has_property(object: &Object, key: &Object) -> Result<bool, RuntimeError> {
+    Ok(match object {
+        Object::Array(list) => match index(key) {
+            Some(i) => i < list.borrow().len(),
+            None => *key.to_string() == "length",
+        },
+        Object::Map(map) => map.borrow().contains_key(&key.to_string()),
+        Object::Closure { .. } => false,
+        primitive => {
+            return Err(RuntimeError::TypeError(format!(
+                "Cannot use 'in' operator to search for '{}' in {}",
+                key.to_string(),
+                primitive.to_string()
+            )))
+        }
+    })
+}
+
+/// Check for `value instanceof constructor`
+fn instance_of(_value: &Object, constructor: &Object) -> Result<bool, RuntimeError> {
+    match constructor {
+        // There are no constructors creating objects yet,
+        // so nothing is an instance of a function
+        Object::Closure { .. } => Ok(false),
+        other => Err(RuntimeError::TypeError(format!(
+            "Right-hand side of 'instanceof' is not callable, got {}",
+            other.to_string()
+        ))),
+    }
+}
+
+/// Elements of `object` for `for ... of`
+fn iterate(object: Object) -> Result<Iteration, RuntimeError> {
+    Ok(match object {
+        Object::Array(array) => Iteration::Array { array, index: 0 },
+        Object::String(s) => {
+            let characters: Vec<_> = s
+                .chars()
+                .map(|c| Object::String(Rc::new(c.to_string())))
+                .collect();
+            Iteration::Values(characters.into_iter())
+        }
+        other => {
+            return Err(RuntimeError::TypeError(format!(
+                "{} is not iterable",
+                other.to_string()
+            )))
+        }
+    })
+}
+
+/// Property names of `object` for `for ... in`.
+/// Index-like keys come first in ascending order, the others follow sorted,
+/// as objects don't remember the order their properties were added in.
+fn keys(object: &Object) -> Vec<Object> {
+    let indices = |length: usize| {
+        (0..length)
+            .map(|i| Object::String(Rc::new(i.to_string())))
+            .collect()
+    };
+
+    match object {
+        Object::Array(list) => indices(list.borrow().len()),
+        Object::String(s) => indices(s.chars().count()),
+        Object::Map(map) => {
+            let mut keys: Vec<_> = map.borrow().keys().cloned().collect();
+            keys.sort_by_key(|key| match key.parse::<u32>() {
+                // `Ok` is ordered before `Err`
+                Ok(i) if *key.as_str() == i.to_string() => Ok(i),
+                _ => Err(key.clone()),
+            });
+            keys.into_iter().map(Object::String).collect()
+        }
+        // Nothing to enumerate, not even for `null` and `undefined`
+        _ => Vec::new(),
+    }
+}
+
+fn set_property(object: &Object, key: &Object, value: Object) -> Result<(), RuntimeError> {
+    match object {
+        Object::Array(list) => {
+            let mut list = list.borrow_mut();
+            match index(key) {
+                Some(i) => {
+                    if i >= list.len() {
+                        list.resize(i + 1, Object::Undefined);
+                    }
+                    list[i] = value;
+                }
+                None if *key.to_string() == "length" => {
+                    list.resize(value.to_number() as usize, Object::Undefined)
+                }
+                None => {}
+            }
+        }
+        Object::Map(map) => {
+            map.borrow_mut().insert(key.to_string(), value);
+        }
+        Object::Undefined | Object::Null => {
+            return Err(RuntimeError::TypeError(format!(
+                "Cannot set property '{}' of {}",
+                key.to_string(),
+                object.to_string()
+            )))
+        }
+        // Properties of primitive values are lost
+        _ => {}
+    }
+
+    Ok(())
+}
+
+/// Remove a property, returning false for properties which can't be removed
+fn delete_property(object: &Object, key: &Object) -> Result<bool, RuntimeError> {
+    Ok(match object {
+        Object::Array(list) => {
+            let mut list = list.borrow_mut();
+            match index(key) {
+                // Arrays have no holes, the element becomes undefined
+                Some(i) if i < list.len() => {
+                    list[i] = Object::Undefined;
+                    true
+                }
+                None if *key.to_string() == "length" => false,
+                _ => true,
+            }
         }
+        Object::Map(map) => {
+            map.borrow_mut().remove(&key.to_string());
+            true
+        }
+        Object::String(s) => match index(key) {
+            Some(i) => i >= s.chars().count(),
+            None => *key.to_string() != "length",
+        },
+        Object::Undefined | Object::Null => {
+            return Err(RuntimeError::TypeError(format!(
+                "Cannot delete property '{}' of {}",
+                key.to_string(),
+                object.to_string()
+            )))
+        }
+        _ => true,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{compile::generate_code, parse::parse};
+
+    fn run(source: &str) -> Result<Object, RuntimeError> {
+        let (rest, ast) = parse(source).unwrap();
+        assert_eq!("", rest.trim());
+        let code = generate_code(&ast).unwrap();
+        VirtualMachine::new(code).run()
+    }
+
+    fn number(source: &str) -> f64 {
+        match run(source) {
+            Ok(Object::Number(n)) => n,
+            other => panic!("expected a number, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn arithmetic() {
+        assert_eq!(7.0, number("return 1 + 2 * 3"));
+        assert_eq!(1.0, number("return 7 % 3"));
+        assert_eq!(8.0, number("return 2 ** 3"));
+        assert!(number("return 1 ** NaN").is_nan());
+        assert!(number("return 1 ** Infinity").is_nan());
+        assert!(number("return (-1) ** -Infinity").is_nan());
+        assert!(number("return NaN ** 1").is_nan());
+        assert_eq!(1.0, number("return NaN ** 0"));
+        assert_eq!(0.0, number("return 0.5 ** Infinity"));
+    }
+
+    #[test]
+    fn bitwise() {
+        assert_eq!(5.0, number("return 1 | 4"));
+        assert_eq!(12.0, number("return 6 & 12 ^ 8"));
+        assert_eq!(-1.0, number("return ~0"));
+        assert_eq!(-2.0, number("return ~1.5"));
+        assert_eq!(8.0, number("return 1 << 35"));
+        assert_eq!(-2.0, number("return -8 >> 2"));
+        assert_eq!(1073741822.0, number("return -8 >>> 2"));
+        assert_eq!(4294967295.0, number("return -1 >>> 0"));
+        assert_eq!(-2147483648.0, number("return 1 << 31"));
+        assert_eq!(1.0, number("return 4294967297 | 0"));
+    }
+
+    #[test]
+    fn equality() {
+        assert_eq!(Object::Boolean(true), run("return 1 == \"1\"").unwrap());
+        assert_eq!(Object::Boolean(false), run("return 1 === \"1\"").unwrap());
+        assert_eq!(Object::Boolean(true), run("return 1 !== \"1\"").unwrap());
+        assert_eq!(
+            Object::Boolean(true),
+            run("let a = [1]; return a === a").unwrap()
+        );
+        assert_eq!(Object::Boolean(false), run("return [1] === [1]").unwrap());
+    }
+
+    #[test]
+    fn relational() {
+        assert_eq!(Object::Boolean(true), run("return 0 in [5]").unwrap());
+        assert_eq!(Object::Boolean(false), run("return 1 in [5]").unwrap());
+        assert_eq!(
+            Object::Boolean(true),
+            run("return \"length\" in []").unwrap()
+        );
+        assert_eq!(
+            Object::Boolean(true),
+            run("return \"x\" in { x: 1 }").unwrap()
+        );
+        assert_eq!(
+            Object::Boolean(false),
+            run("return \"y\" in { x: 1 }").unwrap()
+        );
+        assert!(matches!(
+            run("return 1 in 2"),
+            Err(RuntimeError::TypeError(_))
+        ));
+
+        let source = "
+            function F() {}
+            return [] instanceof F
+        ";
+        assert_eq!(Object::Boolean(false), run(source).unwrap());
+        assert!(matches!(
+            run("return [] instanceof 1"),
+            Err(RuntimeError::TypeError(_))
+        ));
+    }
+
+    #[test]
+    fn bigints() {
+        let bigint = |source| match run(source) {
+            Ok(Object::BigInt(n)) => n,
+            other => panic!("expected a BigInt, got {:?}", other),
+        };
+        assert_eq!(7, bigint("return 1n + 2n * 3n"));
+        assert_eq!(-3, bigint("return -7n / 2n"));
+        assert_eq!(-1, bigint("return -7n % 2n"));
+        assert_eq!(1 << 100, bigint("return 2n ** 100n"));
+        assert_eq!(40, bigint("return 5n << 3n"));
+        assert_eq!(-3, bigint("return -5n >> 1n"));
+        assert_eq!(-6, bigint("return ~5n"));
+        assert_eq!(
+            Ok(Object::String(Rc::new("1n = 1".to_string()))),
+            run("return \"1n = \" + 1n")
+        );
+        assert_eq!(
+            Object::Boolean(true),
+            run("return 2n > 1 && 1n == 1").unwrap()
+        );
+        assert_eq!(Object::Boolean(false), run("return 1n === 1").unwrap());
+
+        assert!(matches!(
+            run("return 1n + 1"),
+            Err(RuntimeError::TypeError(_))
+        ));
+        assert!(matches!(
+            run("return 1n >>> 1n"),
+            Err(RuntimeError::TypeError(_))
+        ));
+        assert!(matches!(
+            run("return 1n / 0n"),
+            Err(RuntimeError::RangeError(_))
+        ));
+        assert!(matches!(
+            run("return 2n ** 127n"),
+            Err(RuntimeError::RangeError(_))
+        ));
+        assert!(matches!(
+            run("return 1n << 127n"),
+            Err(RuntimeError::RangeError(_))
+        ));
+    }
+
+    #[test]
+    fn null_and_undefined() {
+        let string = |s: &str| Ok(Object::String(Rc::new(s.to_string())));
+        assert_eq!(Ok(Object::Null), run("return null"));
+        assert_eq!(Ok(Object::Undefined), run("let x; return x"));
+        assert_eq!(1.0, number("return null + 1"));
+        assert!(number("return undefined + 1").is_nan());
+        assert!(number("return NaN * 0").is_nan());
+        assert_eq!(
+            string("null undefined"),
+            run("return null + \" \" + undefined")
+        );
+
+        assert_eq!(Ok(Object::Boolean(true)), run("return null == undefined"));
+        assert_eq!(Ok(Object::Boolean(false)), run("return null === undefined"));
+        assert_eq!(Ok(Object::Boolean(false)), run("return null == 0"));
+        assert_eq!(Ok(Object::Boolean(true)), run("return null >= 0"));
+        assert_eq!(Ok(Object::Boolean(false)), run("return NaN == NaN"));
+        assert_eq!(Ok(Object::Boolean(true)), run("return !null"));
+
+        assert_eq!(string("object"), run("return typeof null"));
+        assert_eq!(string("undefined"), run("return typeof undefined"));
+        assert_eq!(string("number"), run("return typeof NaN"));
+        assert_eq!(string("function"), run("function f() {}; return typeof f"));
+        assert_eq!(string("undefined"), run("return typeof nope"));
+        assert_eq!(
+            Ok(Object::Boolean(true)),
+            run("return typeof typeof nope === 'string'")
+        );
+
+        assert!(matches!(
+            run("let x = null; return x.y"),
+            Err(RuntimeError::TypeError(_))
+        ));
+    }
+
+    #[test]
+    fn variables() {
+        let source = "
+            let x = 1
+            x += 2
+            let y = x * 2
+            return y
+        ";
+        assert_eq!(6.0, number(source));
+    }
+
+    #[test]
+    fn declarations() {
+        assert_eq!(3.0, number("let a = 1, b = a + 1; return a + b"));
+        assert_eq!(2.0, number("const a = 2; return a"));
+
+        let source = "
+            function f(flag) {
+                if (flag) {
+                    var x = 1
+                }
+                return x
+            }
+            return f(true)
+        ";
+        assert_eq!(1.0, number(source));
+        assert_eq!(
+            Ok(Object::Undefined),
+            run("function f() { return x; var x = 1 } return f()")
+        );
+
+        let source = "
+            var total = 0
+            for (var i = 0; i < 4; i++) total += i
+            return total + i
+        ";
+        assert_eq!(10.0, number(source));
+    }
+
+    #[test]
+    fn string_concatenation() {
+        assert_eq!(
+            Ok(Object::String(Rc::new("a1".to_string()))),
+            run("return \"a\" + 1")
+        );
+    }
+
+    #[test]
+    fn template_literals() {
+        let source = "
+            let x = 2
+            return `${x} * ${x} = ${x * x}`
+        ";
+        assert_eq!(
+            Ok(Object::String(Rc::new("2 * 2 = 4".to_string()))),
+            run(source)
+        );
+
+        let source = "
+            function tag(strings, a, b) {
+                return strings.length * 100 + a * 10 + b
+            }
+            return tag`one ${1} two ${2} three`
+        ";
+        assert_eq!(312.0, number(source));
+
+        let source = "
+            function first(strings) { return strings[0] }
+            return first`line
+next`
+        ";
+        assert_eq!(
+            Ok(Object::String(Rc::new("line\nnext".to_string()))),
+            run(source)
+        );
+
+        let source = "
+            function parts(strings) { return [typeof strings[0], strings[1]] }
+            return parts`\\unicode${0}b`
+        ";
+        assert_eq!("undefined,b", run(source).unwrap().to_string().as_str());
+    }
+
+    #[test]
+    fn conditions() {
+        assert_eq!(1.0, number("if (2 > 1) return 1; else return 2"));
+        assert_eq!(2.0, number("if (2 < 1) { return 1 } else { return 2 }"));
+        assert_eq!(3.0, number("return 0 || 3"));
+        assert_eq!(0.0, number("return 0 && 3"));
+        assert_eq!(4.0, number("return 1 == 2 ? 3 : 4"));
+    }
+
+    #[test]
+    fn loops() {
+        let source = "
+            let sum = 0
+            for (let i = 0; i < 10; i++) {
+                if (i == 5) { continue }
+                if (i == 8) { break }
+                sum += i
+            }
+            return sum
+        ";
+        assert_eq!(23.0, number(source));
+
+        let source = "
+            let i = 0
+            while (i < 100) i += 7
+            return i
+        ";
+        assert_eq!(105.0, number(source));
+
+        let source = "
+            let steps = 0
+            for (let i = 0, j = 10; i < j; i++, j--) steps++
+            let n = 0
+            for (n = 1; n < 100;) n *= 3
+            for (;;) { if (steps > 7) break; steps += 2 }
+            return steps * 1000 + n
+        ";
+        assert_eq!(9243.0, number(source));
+
+        let source = "
+            function capture() {
+                let first
+                let last
+                for (let i = 0; i < 3; i++) {
+                    if (i == 0) first = () => { return i }
+                    last = () => { return i }
+                }
+                return [first, last]
+            }
+            let closures = capture()
+            let first = closures[0]
+            let last = closures[1]
+            return first() * 10 + last()
+        ";
+        assert_eq!(2.0, number(source));
+
+        // Toplevel loops create a binding per iteration as well
+        let source = "
+            let fs = []
+            for (let i = 0; i < 3; i++) { fs[i] = () => { return i } }
+            return fs[0]() + fs[1]() + fs[2]()";
+        assert_eq!(3.0, number(source));
+    }
+
+    #[test]
+    fn labels() {
+        let source = "
+            let pairs = 0
+            outer: for (let i = 0; i < 5; i++) {
+                for (let j = 0; j < 5; j++) {
+                    if (j > i) continue outer
+                    if (i == 3) break outer
+                    pairs++
+                }
+            }
+            return pairs
+        ";
+        assert_eq!(6.0, number(source));
+
+        let source = "
+            let x = 1
+            found: {
+                if (x == 1) break found
+                x = 2
+            }
+            return x
+        ";
+        assert_eq!(1.0, number(source));
+    }
+
+    #[test]
+    fn do_while() {
+        let source = "
+            let n = 10
+            do n++; while (n < 5)
+            let i = 0
+            do {
+                i++
+                if (i == 2) continue
+                n += i
+            } while (i < 4)
+            return n
+        ";
+        assert_eq!(19.0, number(source));
+    }
+
+    #[test]
+    fn switch() {
+        let source = "
+            function name(n) {
+                let result = ''
+                switch (n) {
+                    case 1:
+                        result = 'one'
+                        break
+                    case 2:
+                    case 3:
+                        result = 'few'
+                        break
+                    default:
+                        result = 'many'
+                    case '1':
+                        result += '!'
+                }
+                return result
+            }
+            return name(1) + name(3) + name(7) + name('1')
+        ";
+        assert_eq!(
+            Ok(Object::String(Rc::new("onefewmany!!".to_string()))),
+            run(source)
+        );
+
+        let source = "
+            let one = 1
+            let sum = 0
+            for (let i = 0; i < 4; i++) {
+                switch (i) {
+                    case one: continue
+                    case one + 1: sum += 10
+                    default: sum += i
+                }
+            }
+            return sum
+        ";
+        assert_eq!(15.0, number(source));
+
+        assert_eq!(
+            2.0,
+            number("switch (2) { case 2: let x = 1; default: return x + 1 }")
+        );
+    }
+
+    #[test]
+    fn iteration() {
+        let source = "
+            let sum = 0
+            for (const x of [1, 2, 3, 4]) {
+                if (x == 2) continue
+                sum += x
+            }
+            return sum
+        ";
+        assert_eq!(8.0, number(source));
+
+        let source = "
+            let keys = ''
+            for (var key in { b: 1, a: 2 }) keys += key
+            for (const i in 'xy') keys += i
+            return keys + key
+        ";
+        assert_eq!(
+            Ok(Object::String(Rc::new("ab01b".to_string()))),
+            run(source)
+        );
+
+        let map = ["b", "10", "a", "2"]
+            .iter()
+            .map(|key| (Rc::new(key.to_string()), Object::Null))
+            .collect();
+        let keys: Vec<_> = keys(&Object::Map(Gc::new(map)))
+            .iter()
+            .map(|key| key.to_string().to_string())
+            .collect();
+        assert_eq!(vec!["2", "10", "a", "b"], keys);
+
+        let source = "
+            function first() {
+                let first
+                for (let c of 'abc') {
+                    if (c == 'a') first = () => { return c }
+                }
+                return first
+            }
+            let f = first()
+            return f()
+        ";
+        assert_eq!(Ok(Object::String(Rc::new("a".to_string()))), run(source));
+
+        // Toplevel loops create a binding per iteration as well
+        let source = "
+            let fs = []
+            for (const x of [1, 2, 3]) fs[fs.length] = () => { return x }
+            return fs[0]() + fs[1]() + fs[2]()";
+        assert_eq!(6.0, number(source));
+
+        // Existing variables and properties are assigned
+        let source = "
+            let x, keys = ''
+            let o = { k: 0 }
+            for (x of [1, 2, 3]) o.k += x
+            for (o.last of [4, 5]) {}
+            for (keys in [7, 8]) {}
+            return [x, o.k, o.last, keys]";
+        assert_eq!("3,6,5,1", run(source).unwrap().to_string().as_str());
+
+        assert_eq!(0.0, number("let n = 0; for (let k in null) n++; return n"));
+        assert!(matches!(
+            run("for (let x of 12) {}"),
+            Err(RuntimeError::TypeError(_))
+        ));
+    }
+
+    #[test]
+    fn functions() {
+        let source = "
+            function square(x) { return x*x }
+            return square(3) + square(4)
+        ";
+        assert_eq!(25.0, number(source));
+    }
+
+    #[test]
+    fn recursion() {
+        let source = "
+            function fib(n) {
+                if (n < 2) return n
+                return fib(n - 1) + fib(n - 2)
+            }
+            return fib(15)
+        ";
+        assert_eq!(610.0, number(source));
+    }
+
+    #[test]
+    fn closures() {
+        let source = "
+            function counter() {
+                let count = 0
+                return () => {
+                    count += 1
+                    return count
+                }
+            }
+            let next = counter()
+            next()
+            next()
+            return next()
+        ";
+        assert_eq!(3.0, number(source));
+
+        assert_eq!(5.0, number("let f = () => 5; return f()"));
+        assert_eq!(3.0, number("let n = 1; let f = () => n + 2; return f()"));
+        assert!(matches!(
+            run("let f = () => {}; return f()"),
+            Ok(Object::Undefined)
+        ));
+    }
+
+    #[test]
+    fn objects() {
+        let source = "
+            let player = {
+                health: 100,
+                position: { x: 1, y: 2 }
+            }
+            player.position.x++
+            return player.health + player.position.x
+        ";
+        assert_eq!(102.0, number(source));
+
+        assert_eq!(3.0, number("let list = [1, 2, 3]; return list.length"));
+        assert_eq!(2.0, number("let list = [1, 2, 3]; return list[1]"));
+    }
+
+    #[test]
+    fn optional_chains() {
+        let source = "
+            let calls = 0
+            function count() { calls++; return 1 }
+            let config = { server: { port: 80 }, list: [1, 2], get: () => { return 3 } }
+            let missing = null
+            let sum = config?.server.port + config.list?.[1] + config.get?.()
+            if (missing?.server.port === undefined) { sum = sum + 100 }
+            if (config.nothing?.[count()].x === undefined) { sum = sum + 1000 }
+            if (config.nothing?.(count()) === undefined) { sum = sum + 10000 }
+            return sum + calls";
+        assert_eq!(11185.0, number(source));
+
+        // Parentheses end the chain
+        assert!(matches!(
+            run("let a; return (a?.b).c"),
+            Err(RuntimeError::TypeError(_))
+        ));
+    }
+
+    #[test]
+    fn nullish() {
+        let source = "
+            let calls = 0
+            function count() { calls++; return 1 }
+            let a = null ?? 1
+            let b = 0 ?? count()
+            let c = undefined ?? false ?? count()
+            return [a, b, c, calls]";
+        assert_eq!("1,0,false,0", run(source).unwrap().to_string().as_str());
+
+        let source = "
+            let calls = 0
+            function count() { calls++; return 5 }
+            let x
+            let settings = { step: 0 }
+            x ??= count()
+            x ??= count()
+            settings.step ??= count()
+            settings.limit ??= count()
+            return [x, settings.step, settings.limit, calls]";
+        assert_eq!("5,0,5,2", run(source).unwrap().to_string().as_str());
+    }
+
+    #[test]
+    fn comma_operator() {
+        let source = "
+            let a = 1, b = 1
+            a++, b += 10
+            let x = (a, b * 2)
+            if (a--, a) x++
+            return x";
+        assert_eq!(23.0, number(source));
+        assert_eq!(2.0, number("return 1, 2"));
+    }
+
+    #[test]
+    fn compound_assignment() {
+        let source = "
+            let a = 2, b = 1, c = -16, d = 6, e = 6, f = 6, g = 5
+            a **= 3
+            b <<= 4
+            c >>= 2
+            d &= 3
+            e |= 1
+            f ^= 3
+            g -= 1
+            return [a, b, c, d, e, f, g]";
+        assert_eq!("8,16,-4,2,7,5,4", run(source).unwrap().to_string().as_str());
+        assert_eq!(1073741820.0, number("let x = -16; x >>>= 2; return x"));
+    }
+
+    #[test]
+    fn logical_assignment() {
+        let source = "
+            let calls = 0
+            function count() { calls++; return 'set' }
+            let a = 1, b = 0, c = 1, d = 0
+            let flags = { on: true }
+            a &&= count()
+            b &&= count()
+            c ||= count()
+            d ||= count()
+            flags.on &&= false
+            flags.off ||= count()
+            return [a, b, c, d, flags.on, flags.off, calls]";
+        assert_eq!(
+            "set,0,1,set,false,set,3",
+            run(source).unwrap().to_string().as_str()
+        );
+    }
+
+    #[test]
+    fn increments() {
+        let source = "
+            let x = 1, y = 1
+            let point = { x: 1 }, list = [1]
+            return [++x, x, y++, y, --point.x, point.x, list[0]--, list[0], -x++, x]";
+        assert_eq!(
+            "2,2,1,2,0,0,1,0,-2,3",
+            run(source).unwrap().to_string().as_str()
+        );
+        // The increment belongs to the following line
+        assert_eq!(2.0, number("let a = 1, b = 1\na\n++b\nreturn a + b - 1"));
+
+        let source = "
+            let s = '5', t = '5', o = { a: '5' }
+            return [s++, s, ++t, t, o.a++, o.a, --o.a]";
+        assert_eq!("5,6,6,6,5,6,5", run(source).unwrap().to_string().as_str());
+        assert_eq!(
+            Ok(Object::String(Rc::new("number".to_string()))),
+            run("let s = '5'; return typeof s++")
+        );
+        assert!(number("let s = 'x'; s++; return s").is_nan());
+
+        let source = "
+            let x = 1n, y = 1n
+            let o = { a: 5n, b: 5n }
+            return [x++, x, ++y, y, o.a--, o.a, --o.b, o.b, typeof x]";
+        assert_eq!(
+            "1,2,2,2,5,4,4,4,bigint",
+            run(source).unwrap().to_string().as_str()
+        );
+    }
+
+    #[test]
+    fn unary() {
+        assert_eq!(3.0, number("return +'3'"));
+        assert_eq!(1.0, number("return +true"));
+        assert!(number("return +'x'").is_nan());
+        assert!(matches!(run("return +1n"), Err(RuntimeError::TypeError(_))));
+        assert_eq!(-6.0, number("return ~5"));
+        assert_eq!(
+            Ok(Object::String(Rc::new("number".to_string()))),
+            run("return typeof +'1'")
+        );
+
+        let source = "
+            let calls = 0
+            function count() { calls++ }
+            let v = void count()
+            return v === undefined && calls === 1";
+        assert_eq!(Ok(Object::Boolean(true)), run(source));
+    }
+
+    #[test]
+    fn delete() {
+        let source = "
+            let point = { x: 1, y: 2 }
+            let list = [1, 2, 3]
+            let missing
+            let results = [delete point.x, delete list[1], delete point, delete missing?.x, delete list.length]
+            return [results, 'x' in point, point.y, list.length, list[1]]";
+        assert_eq!(
+            "true,true,false,true,false,false,2,3,",
+            run(source).unwrap().to_string().as_str()
+        );
+        assert!(matches!(
+            run("let x; return delete x.y"),
+            Err(RuntimeError::TypeError(_))
+        ));
+    }
+
+    #[test]
+    fn member_chains() {
+        let source = "
+            function counter() {
+                let n = 0
+                return { next: () => { n++; return n } }
+            }
+            let grid = [[1, 2], [3, 4]]
+            grid[1][0] = 10
+            grid[0][1] += 5
+            grid[1][1]++
+            let c = counter()
+            c.next()
+            return grid[0][1] + grid[1][0] + grid[1][1] + c.next() + ({ x: 100 }).x";
+        assert_eq!(124.0, number(source));
+        assert_eq!(
+            3.0,
+            number("let o = { default: 1 }; o.delete = 2; return o.default + o.delete")
+        );
+
+        let source = "
+            function pair(a) { return [a, a + 1] }
+            function three() { return () => { return 3 } }
+            let settings = {}
+            settings.step = pair(1)[1] + three()()
+            settings['twice'] = settings.step * 2
+            return settings.twice";
+        assert_eq!(10.0, number(source));
+    }
+
+    #[test]
+    fn exceptions() {
+        let source = "
+            let log = ''
+            try {
+                log = log + 'a'
+                throw 'b'
+                log = log + 'x'
+            } catch (e) {
+                log = log + e
+            } finally {
+                log = log + 'c'
+            }
+            try { log = log + 'd' } catch { log = log + 'x' }
+            return log";
+        assert_eq!(Ok(Object::String(Rc::new("abcd".to_string()))), run(source));
+
+        let source = "
+            function f(x) {
+                if (x > 2) { throw x }
+                return x
+            }
+            let sum = 0
+            for (let i = 0; i < 5; i = i + 1) {
+                try { sum = sum + f(i) } catch (e) { sum = sum + 10 * e }
+            }
+            return sum";
+        assert_eq!(73.0, number(source));
+
+        // Errors of the machine itself are caught as well
+        let source = "
+            try { let x; x.y } catch (e) { return e.name }";
+        assert_eq!(
+            Ok(Object::String(Rc::new("TypeError".to_string()))),
+            run(source)
+        );
+    }
+
+    #[test]
+    fn finally() {
+        let source = "
+            let x = 1
+            function f() {
+                try { return x } finally { x = 2 }
+            }
+            return f() * 10 + x";
+        assert_eq!(12.0, number(source));
+
+        let source = "
+            let n = 0
+            while (true) {
+                try {
+                    try { break } finally { n = n + 1 }
+                } finally {
+                    n = n + 10
+                }
+            }
+            return n";
+        assert_eq!(11.0, number(source));
+
+        let source = "
+            let n = 0
+            try {
+                try { throw 1 } finally { n = n + 1 }
+            } catch (e) {
+                n = n + 10 * e
+            }
+            try {
+                try { n = n + 1 } catch { } finally { throw 100 }
+            } catch (e) {
+                n = n + e
+            }
+            return n";
+        assert_eq!(112.0, number(source));
+
+        // The `finally` block overrides the value returned
+        assert_eq!(
+            2.0,
+            number("function f() { try { return 1 } finally { return 2 } } return f()")
+        );
+    }
+
+    #[test]
+    fn uncaught() {
+        assert_eq!(
+            Err(RuntimeError::Exception(Object::Number(1.0))),
+            run("throw 1")
+        );
+        assert_eq!(
+            Err(RuntimeError::Exception(Object::Number(2.0))),
+            run("try { throw 1 } catch (e) { throw e + 1 }")
+        );
+        assert_eq!(
+            Err(RuntimeError::Exception(Object::Number(1.0))),
+            run("let n = 0; try { throw 1 } finally { n = 1 }")
+        );
+        assert_eq!(
+            "Uncaught 1",
+            RuntimeError::Exception(Object::Number(1.0)).to_string()
+        );
     }
 
-    fn op_add(&mut self) {
-        let right = self.stack.pop();
-        let left = self.stack.pop();
-        // stack.push(left + right)
+    #[test]
+    fn errors() {
+        assert!(matches!(
+            run("let x = 1; return x()"),
+            Err(RuntimeError::TypeError(_))
+        ));
+        assert!(matches!(
+            run("let x; return x.y"),
+            Err(RuntimeError::TypeError(_))
+        ));
+        assert!(matches!(
+            run("function f() { return f() } return f()"),
+            Err(RuntimeError::RangeError(_))
+        ));
     }
 }
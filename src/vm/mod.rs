@@ -2,6 +2,6 @@ mod instruction;
 mod machine;
 mod object;
 
-pub use instruction::Instruction;
-pub use machine::VirtualMachine;
-pub use object::Object;
+pub use instruction::{Capture, Instruction, InstructionAddress, StackAddress};
+pub use machine::{RuntimeError, VirtualMachine};
+pub use object::{Gc, Iteration, Object};
@@ -1,38 +1,239 @@
 use crate::vm::instruction::InstructionAddress;
+use std::cell::{Ref, RefCell, RefMut};
 use std::collections::HashMap;
-use std::ops::{Add, Sub};
+use std::ops::Add;
 use std::rc::Rc;
 
 // TODO use GC
-#[derive(Debug, Clone)]
-struct Gc<T>(T);
+/// Shared, mutable value. Clones point to the same value.
+#[derive(Debug)]
+pub struct Gc<T>(Rc<RefCell<T>>);
+
+impl<T> Gc<T> {
+    pub fn new(value: T) -> Gc<T> {
+        Gc(Rc::new(RefCell::new(value)))
+    }
+
+    pub fn borrow(&self) -> Ref<'_, T> {
+        self.0.borrow()
+    }
+
+    pub fn borrow_mut(&self) -> RefMut<'_, T> {
+        self.0.borrow_mut()
+    }
+}
+
+impl<T> Clone for Gc<T> {
+    fn clone(&self) -> Gc<T> {
+        Gc(self.0.clone())
+    }
+}
+
+/// Values are equal, if they are the very same
+impl<T> PartialEq for Gc<T> {
+    fn eq(&self, other: &Gc<T>) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+}
 
 /// Garbage Collected JavaScript Object
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Object {
+    Undefined,
+    Null,
     Boolean(bool),
     Number(f64),
+    /// Signed 128 bit integer, overflowing it is a `RangeError`
+    BigInt(i128),
     String(Rc<String>),
     Array(Gc<Vec<Object>>),
-    Map(HashMap<Rc<String>, Object>),
+    Map(Gc<HashMap<Rc<String>, Object>>),
     Closure {
-        enviroment: Rc<Vec<Object>>,
+        enviroment: Rc<Vec<Gc<Object>>>,
         function: InstructionAddress,
     },
+    /// Variable shared between a function and the closures capturing it.
+    /// Only ever found in variable slots, never visible to scripts.
+    Reference(Gc<Object>),
+    /// Progress of a `for ... of` or `for ... in` loop.
+    /// Only ever found in temporary slots, never visible to scripts.
+    Iterator(Gc<Iteration>),
+}
+
+/// Values a loop iterates over
+#[derive(Debug)]
+pub enum Iteration {
+    /// Elements of an array, including those added while iterating
+    Array {
+        array: Gc<Vec<Object>>,
+        index: usize,
+    },
+    /// Values fixed once the loop starts, like the keys of an object
+    Values(std::vec::IntoIter<Object>),
+}
+
+impl Iterator for Iteration {
+    type Item = Object;
+
+    fn next(&mut self) -> Option<Object> {
+        match self {
+            Iteration::Array { array, index } => {
+                let element = array.borrow().get(*index).cloned()?;
+                *index += 1;
+                Some(element)
+            }
+            Iteration::Values(values) => values.next(),
+        }
+    }
 }
 
 impl Object {
-    fn to_string(&self) -> Rc<String> {
+    pub fn to_string(&self) -> Rc<String> {
         use Object::*;
         match self {
+            Undefined => Rc::new("undefined".to_string()),
+            Null => Rc::new("null".to_string()),
             Boolean(b) => Rc::new(b.to_string()),
-            Number(n) => Rc::new(n.to_string()),
+            Number(n) => Rc::new(number_to_string(*n)),
+            BigInt(n) => Rc::new(n.to_string()),
             String(s) => s.clone(),
-            _ => Rc::new("Object".to_string()),
+            Array(list) => Rc::new(
+                list.borrow()
+                    .iter()
+                    .map(|o| match o {
+                        Undefined | Null => std::string::String::new(),
+                        o => o.to_string().to_string(),
+                    })
+                    .collect::<Vec<_>>()
+                    .join(","),
+            ),
+            Closure { .. } => Rc::new("function".to_string()),
+            Reference(value) => value.borrow().to_string(),
+            _ => Rc::new("[object Object]".to_string()),
+        }
+    }
+
+    pub fn to_number(&self) -> f64 {
+        use Object::*;
+        match self {
+            Null => 0.0,
+            Boolean(b) => *b as u8 as f64,
+            Number(n) => *n,
+            BigInt(n) => *n as f64,
+            String(s) => string_to_number(s),
+            Array(_) => string_to_number(&self.to_string()),
+            Reference(value) => value.borrow().to_number(),
+            _ => f64::NAN,
+        }
+    }
+
+    /// Conversion for bitwise operators, wrapping around modulo 2^32
+    pub fn to_int32(&self) -> i32 {
+        self.to_uint32() as i32
+    }
+
+    /// Conversion for `>>>`, wrapping around modulo 2^32
+    pub fn to_uint32(&self) -> u32 {
+        let n = self.to_number();
+        if n.is_finite() {
+            n.trunc().rem_euclid(4294967296.0) as u32
+        } else {
+            0
+        }
+    }
+
+    pub fn truthy(&self) -> bool {
+        use Object::*;
+        match self {
+            Undefined | Null => false,
+            Boolean(b) => *b,
+            Number(n) => *n != 0.0 && !n.is_nan(),
+            BigInt(n) => *n != 0,
+            String(s) => !s.is_empty(),
+            Reference(value) => value.borrow().truthy(),
+            _ => true,
+        }
+    }
+
+    /// Abstract equality of `==`,
+    /// operands of different types are converted to a common type first
+    pub fn equals(&self, other: &Object) -> bool {
+        use Object::*;
+        match (self, other) {
+            _ if std::mem::discriminant(self) == std::mem::discriminant(other) => {
+                self.strict_equals(other)
+            }
+            (Undefined, Null) | (Null, Undefined) => true,
+            (Undefined, _) | (_, Undefined) | (Null, _) | (_, Null) => false,
+            (Boolean(_), _) => Number(self.to_number()).equals(other),
+            (_, Boolean(_)) => self.equals(&Number(other.to_number())),
+            (Number(_) | BigInt(_), String(_))
+            | (String(_), Number(_) | BigInt(_))
+            | (Number(_), BigInt(_))
+            | (BigInt(_), Number(_)) => self.to_number() == other.to_number(),
+            // Objects are compared to primitives by their primitive value
+            (Number(_) | BigInt(_) | String(_), _) => self.equals(&other.to_primitive()),
+            (_, Number(_) | BigInt(_) | String(_)) => self.to_primitive().equals(other),
+            _ => false,
+        }
+    }
+
+    /// Strict equality of `===`, values of different types are never equal.
+    /// Objects are only equal to themselves.
+    pub fn strict_equals(&self, other: &Object) -> bool {
+        use Object::*;
+        match (self, other) {
+            (Undefined, Undefined) | (Null, Null) => true,
+            (Boolean(a), Boolean(b)) => a == b,
+            // `NaN` is not equal to itself, `0` equals `-0`
+            (Number(a), Number(b)) => a == b,
+            (BigInt(a), BigInt(b)) => a == b,
+            (String(a), String(b)) => a == b,
+            (Array(a), Array(b)) => a == b,
+            (Map(a), Map(b)) => a == b,
+            (Closure { .. }, Closure { .. }) => self == other,
+            (Reference(a), b) => a.borrow().strict_equals(b),
+            (a, Reference(b)) => a.strict_equals(&b.borrow()),
+            _ => false,
+        }
+    }
+
+    /// Result of `typeof`
+    pub fn type_of(&self) -> &'static str {
+        use Object::*;
+        match self {
+            Undefined => "undefined",
+            Boolean(_) => "boolean",
+            Number(_) => "number",
+            BigInt(_) => "bigint",
+            String(_) => "string",
+            Closure { .. } => "function",
+            Reference(value) => value.borrow().type_of(),
+            Null | Array(_) | Map(_) | Iterator(_) => "object",
+        }
+    }
+
+    /// Primitive value of objects, used for comparisons with primitives
+    fn to_primitive(&self) -> Object {
+        match self {
+            Object::Array(_) | Object::Map(_) | Object::Closure { .. } => {
+                Object::String(self.to_string())
+            }
+            primitive => primitive.clone(),
+        }
+    }
+
+    /// Strings are compared lexicographically, everything else as numbers.
+    /// Comparisons including `NaN` result in `None`.
+    pub fn compare(&self, other: &Object) -> Option<std::cmp::Ordering> {
+        match (self, other) {
+            (Object::String(a), Object::String(b)) => Some(a.cmp(b)),
+            (Object::BigInt(a), Object::BigInt(b)) => Some(a.cmp(b)),
+            (a, b) => a.to_number().partial_cmp(&b.to_number()),
         }
     }
 }
-/*
+
 impl Add for Object {
     type Output = Object;
 
@@ -42,6 +243,164 @@ impl Add for Object {
         match (self, o) {
             (String(s), o) => String(Rc::new(format!("{}{}", s, o.to_string()))),
             (s, String(o)) => String(Rc::new(format!("{}{}", s.to_string(), o))),
+            (a @ Array(_), b) | (a @ Map(_), b) | (a, b @ Array(_)) | (a, b @ Map(_)) => {
+                String(Rc::new(format!("{}{}", a.to_string(), b.to_string())))
+            }
+            (a, b) => Number(a.to_number() + b.to_number()),
+        }
+    }
+}
+
+/// Like `Number.prototype.toString`,
+/// which switches to exponent form below 1e-6 and from 1e21 on
+fn number_to_string(n: f64) -> String {
+    if n.is_nan() {
+        return "NaN".to_string();
+    } else if n.is_infinite() {
+        return if n > 0.0 { "Infinity" } else { "-Infinity" }.to_string();
+    } else if n == 0.0 {
+        // Also covers -0
+        return "0".to_string();
+    }
+
+    // Both forms use the shortest digits, which read back as `n`
+    let scientific = format!("{:e}", n);
+    let (mantissa, exponent) = scientific.split_at(scientific.find('e').unwrap());
+    match exponent[1..].parse::<i32>().unwrap() {
+        exponent if exponent >= 21 => format!("{}e+{}", mantissa, exponent),
+        exponent if exponent < -6 => format!("{}e{}", mantissa, exponent),
+        _ => n.to_string(),
+    }
+}
+
+fn string_to_number(s: &str) -> f64 {
+    let s = s.trim();
+    match s {
+        "" => 0.0,
+        "Infinity" | "+Infinity" => f64::INFINITY,
+        "-Infinity" => f64::NEG_INFINITY,
+        // Rust would accept these, JavaScript doesn't
+        s if s.contains(|c: char| c.is_alphabetic() && c != 'e' && c != 'E') => f64::NAN,
+        s => s.parse().unwrap_or(f64::NAN),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Object;
+    use std::rc::Rc;
+
+    fn string(s: &str) -> Object {
+        Object::String(Rc::new(s.to_string()))
+    }
+
+    #[test]
+    fn add() {
+        assert_eq!(
+            Object::Number(3.0),
+            Object::Number(1.0) + Object::Number(2.0)
+        );
+        assert_eq!(string("a1"), string("a") + Object::Number(1.0));
+        assert_eq!(string("1a"), Object::Number(1.0) + string("a"));
+        assert_eq!(
+            Object::Number(2.0),
+            Object::Boolean(true) + Object::Number(1.0)
+        );
+    }
+
+    #[test]
+    fn to_number() {
+        assert_eq!(12.0, string(" 12 ").to_number());
+        assert_eq!(0.0, string("").to_number());
+        assert!(string("inf").to_number().is_nan());
+        assert!(Object::Undefined.to_number().is_nan());
+    }
+
+    #[test]
+    fn to_int32() {
+        assert_eq!(-1, Object::Number(4294967295.0).to_int32());
+        assert_eq!(-3, Object::Number(-3.7).to_int32());
+        assert_eq!(0, Object::Number(f64::NAN).to_int32());
+        assert_eq!(0, Object::Number(f64::INFINITY).to_int32());
+        assert_eq!(12, string("12").to_int32());
+        assert_eq!(4294967293, Object::Number(-3.0).to_uint32());
+    }
+
+    #[test]
+    fn to_string() {
+        assert_eq!("1", *Object::Number(1.0).to_string());
+        assert_eq!("0.5", *Object::Number(0.5).to_string());
+        assert_eq!("-Infinity", *Object::Number(-1.0 / 0.0).to_string());
+        assert_eq!("NaN", *Object::Number(f64::NAN).to_string());
+
+        let cases = [
+            (1e21, "1e+21"),
+            (-1.5e300, "-1.5e+300"),
+            (1e20, "100000000000000000000"),
+            (123456789012345680000.0, "123456789012345680000"),
+            (1e-7, "1e-7"),
+            (-2.5e-10, "-2.5e-10"),
+            (0.000001, "0.000001"),
+            (f64::MAX, "1.7976931348623157e+308"),
+            (5e-324, "5e-324"),
+        ];
+        for (number, expected) in cases {
+            assert_eq!(expected, *Object::Number(number).to_string());
         }
     }
-} */
+
+    #[test]
+    fn truthy() {
+        assert!(!Object::Number(0.0).truthy());
+        assert!(!string("").truthy());
+        assert!(string("0").truthy());
+        assert!(!Object::Undefined.truthy());
+    }
+
+    #[test]
+    fn equals() {
+        assert!(Object::Number(1.0).equals(&string("1")));
+        assert!(Object::Boolean(true).equals(&Object::Number(1.0)));
+        assert!(Object::Boolean(true).equals(&string("1")));
+        assert!(!Object::Boolean(true).equals(&string("true")));
+        assert!(!Object::Undefined.equals(&Object::Number(0.0)));
+        assert!(!Object::Number(f64::NAN).equals(&Object::Number(f64::NAN)));
+
+        let array = |elements| Object::Array(super::Gc::new(elements));
+        assert!(array(vec![]).equals(&Object::Number(0.0)));
+        assert!(array(vec![Object::Number(1.0), Object::Number(2.0)]).equals(&string("1,2")));
+        assert!(!array(vec![]).equals(&array(vec![])));
+    }
+
+    #[test]
+    fn bigint() {
+        assert_eq!("12", *Object::BigInt(12).to_string());
+        assert!(!Object::BigInt(0).truthy());
+        assert!(Object::BigInt(1).equals(&Object::Number(1.0)));
+        assert!(Object::BigInt(1).equals(&string("1")));
+        assert!(Object::BigInt(1).equals(&Object::Boolean(true)));
+        assert!(!Object::BigInt(1).strict_equals(&Object::Number(1.0)));
+        assert!(Object::BigInt(1).strict_equals(&Object::BigInt(1)));
+    }
+
+    #[test]
+    fn null() {
+        assert_eq!("null", *Object::Null.to_string());
+        assert_eq!(0.0, Object::Null.to_number());
+        assert!(!Object::Null.truthy());
+        assert!(Object::Null.equals(&Object::Undefined));
+        assert!(!Object::Null.equals(&Object::Number(0.0)));
+        assert!(!Object::Null.equals(&Object::Boolean(false)));
+        assert!(!Object::Null.strict_equals(&Object::Undefined));
+        assert_eq!("object", Object::Null.type_of());
+    }
+
+    #[test]
+    fn strict_equals() {
+        assert!(!Object::Number(1.0).strict_equals(&string("1")));
+        assert!(Object::Number(0.0).strict_equals(&Object::Number(-0.0)));
+        assert!(!Object::Number(f64::NAN).strict_equals(&Object::Number(f64::NAN)));
+        assert!(string("a").strict_equals(&string("a")));
+        assert!(!Object::Undefined.strict_equals(&Object::Boolean(false)));
+    }
+}